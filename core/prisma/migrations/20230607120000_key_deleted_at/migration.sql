@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "key" ADD COLUMN "deleted_at" DATETIME;
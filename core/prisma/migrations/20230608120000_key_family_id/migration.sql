@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "key" ADD COLUMN "key_family_id" TEXT;
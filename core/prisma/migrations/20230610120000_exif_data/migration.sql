@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "exif_data" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "date_taken" DATETIME,
+    "latitude" REAL,
+    "longitude" REAL,
+    "camera_make" TEXT,
+    "camera_model" TEXT,
+    "orientation" INTEGER,
+    CONSTRAINT "exif_data_id_fkey" FOREIGN KEY ("id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "exif_data_latitude_longitude_idx" ON "exif_data"("latitude", "longitude");
@@ -0,0 +1,30 @@
+-- CreateVirtualTable
+CREATE VIRTUAL TABLE IF NOT EXISTS "search_fts" USING fts5("name", "note", tokenize = 'unicode61 remove_diacritics 2');
+
+-- CreateTrigger
+CREATE TRIGGER IF NOT EXISTS "file_path_fts_insert" AFTER INSERT ON "file_path" BEGIN
+    INSERT INTO "search_fts" ("rowid", "name", "note")
+        VALUES (new."id", new."name", (SELECT "note" FROM "object" WHERE "id" = new."object_id"));
+END;
+
+-- CreateTrigger
+CREATE TRIGGER IF NOT EXISTS "file_path_fts_update" AFTER UPDATE OF "name", "object_id" ON "file_path" BEGIN
+    UPDATE "search_fts" SET "name" = new."name", "note" = (SELECT "note" FROM "object" WHERE "id" = new."object_id")
+        WHERE "rowid" = new."id";
+END;
+
+-- CreateTrigger
+CREATE TRIGGER IF NOT EXISTS "file_path_fts_delete" AFTER DELETE ON "file_path" BEGIN
+    DELETE FROM "search_fts" WHERE "rowid" = old."id";
+END;
+
+-- CreateTrigger
+CREATE TRIGGER IF NOT EXISTS "object_fts_update" AFTER UPDATE OF "note" ON "object" BEGIN
+    UPDATE "search_fts" SET "note" = new."note"
+        WHERE "rowid" IN (SELECT "id" FROM "file_path" WHERE "object_id" = new."id");
+END;
+
+-- PopulateVirtualTable
+INSERT INTO "search_fts" ("rowid", "name", "note")
+    SELECT "file_path"."id", "file_path"."name", "object"."note" FROM "file_path"
+        LEFT JOIN "object" ON "object"."id" = "file_path"."object_id";
@@ -2,9 +2,12 @@ use crate::{
 	api::utils::library,
 	invalidate_query,
 	location::{file_path_helper::IsolatedFilePathData, find_location, LocationError},
-	object::fs::{
-		copy::FileCopierJobInit, cut::FileCutterJobInit, decrypt::FileDecryptorJobInit,
-		delete::FileDeleterJobInit, encrypt::FileEncryptorJobInit, erase::FileEraserJobInit,
+	object::{
+		duplicates::find_duplicates,
+		fs::{
+			copy::FileCopierJobInit, cut::FileCutterJobInit, decrypt::FileDecryptorJobInit,
+			delete::FileDeleterJobInit, encrypt::FileEncryptorJobInit, erase::FileEraserJobInit,
+		},
 	},
 	prisma::{location, object},
 };
@@ -16,7 +19,7 @@ use specta::Type;
 use std::path::Path;
 use tokio::fs;
 
-use super::{Ctx, R};
+use super::{search::SortOrder, Ctx, R};
 
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
@@ -36,6 +39,28 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?)
 				})
 		})
+		.procedure("findDuplicates", {
+			#[derive(Type, Deserialize)]
+			pub struct FindDuplicatesArgs {
+				pub location_id: i32,
+				/// Defaults to the groups that waste the most space first
+				pub order: Option<SortOrder>,
+				pub skip: Option<u32>,
+				pub take: Option<u32>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: FindDuplicatesArgs| async move {
+					Ok(find_duplicates(
+						&library.db,
+						args.location_id,
+						args.order.unwrap_or(SortOrder::Desc).into(),
+						args.skip.unwrap_or(0) as usize,
+						args.take.unwrap_or(100) as usize,
+					)
+					.await?)
+				})
+		})
 		.procedure("setNote", {
 			#[derive(Type, Deserialize)]
 			pub struct SetNoteArgs {
@@ -3,7 +3,7 @@ use crate::{
 	location::{find_location, LocationError},
 	object::{
 		file_identifier::file_identifier_job::FileIdentifierJobInit,
-		preview::thumbnailer_job::ThumbnailerJobInit,
+		preview::thumbnailer_job::{ThumbnailerJob, ThumbnailerJobInit},
 		validation::validator_job::ObjectValidatorJobInit,
 	},
 };
@@ -84,6 +84,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							location_id: args.id,
 							path: args.path,
 							background: true,
+							hashing: Default::default(),
 						})
 						.await
 						.map_err(Into::into)
@@ -127,4 +128,35 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					}
 				})
 		})
+		.procedure("thumbnailProgress", {
+			R.with2(library())
+				.subscription(|(_, library), _: ()| async move {
+					let mut progress_rx = ThumbnailerJob::progress(&library);
+					async_stream::stream! {
+						let progress = progress_rx.borrow_and_update().clone();
+						yield progress;
+
+						while progress_rx.changed().await.is_ok() {
+							let progress = progress_rx.borrow_and_update().clone();
+							yield progress;
+						}
+					}
+				})
+		})
+		.procedure("indexingReport", {
+			R.with2(library())
+				.subscription(|(ctx, library), _: ()| async move {
+					let mut event_bus_rx = ctx.event_bus.0.subscribe();
+					async_stream::stream! {
+						while let Ok(event) = event_bus_rx.recv().await {
+							match event {
+								CoreEvent::IndexingReport { library_id, report } if library_id == library.id => {
+									yield report;
+								}
+								_ => {}
+							}
+						}
+					}
+				})
+		})
 }
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use rspc::alpha::AlphaRouter;
 use rspc::ErrorCode;
 use sd_crypto::keys::keymanager::{StoredKey, StoredKeyType};
@@ -11,8 +12,7 @@ use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use uuid::Uuid;
 
-use crate::util::db::write_storedkey_to_db;
-use crate::{invalidate_query, prisma::key};
+use crate::{invalidate_query, library::KeyStore, prisma::key};
 
 use super::utils::library;
 use super::{Ctx, R};
@@ -52,6 +52,12 @@ pub struct AutomountUpdateArgs {
 	status: bool,
 }
 
+#[derive(Type, Deserialize)]
+pub struct ExpiryUpdateArgs {
+	uuid: Uuid,
+	expires_at: Option<DateTime<Utc>>,
+}
+
 pub(crate) fn mount() -> AlphaRouter<Ctx> {
 	R.router()
 		.procedure("list", {
@@ -73,7 +79,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			R.with2(library())
 				.mutation(|(_, library), config: OnboardingConfig| async move {
 					let root_key = library.key_manager.onboarding(config, library.id).await?;
-					write_storedkey_to_db(&library.db, &root_key).await?;
+					library.key_store().put(&root_key).await?;
 					library
 						.key_manager
 						.populate_keystore(vec![root_key])
@@ -156,8 +162,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				.mutation(|(_, library), key_uuid: Uuid| async move {
 					let key = library.key_manager.sync_to_database(key_uuid).await?;
 
-					// does not check that the key doesn't exist before writing
-					write_storedkey_to_db(&library.db, &key).await?;
+					library.key_store().put(&key).await?;
 
 					invalidate_query!(library, "keys.list");
 					Ok(())
@@ -188,16 +193,31 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("updateExpiry", {
+			R.with2(library())
+				.mutation(|(_, library), args: ExpiryUpdateArgs| async move {
+					// the database is written first, so a failed write doesn't leave the key manager ahead of it
+					if !library.key_manager.is_memory_only(args.uuid).await? {
+						library
+							.key_store()
+							.set_expiry(args.uuid, args.expires_at)
+							.await?;
+					}
+
+					library
+						.key_manager
+						.change_expiry(args.uuid, args.expires_at)
+						.await?;
+
+					invalidate_query!(library, "keys.list");
+					Ok(())
+				})
+		})
 		.procedure("deleteFromLibrary", {
 			R.with2(library())
 				.mutation(|(_, library), key_uuid: Uuid| async move {
 					if !library.key_manager.is_memory_only(key_uuid).await? {
-						library
-							.db
-							.key()
-							.delete(key::uuid::equals(key_uuid.to_string()))
-							.exec()
-							.await?;
+						library.key_store().remove(key_uuid).await?;
 					}
 
 					library.key_manager.remove_key(key_uuid).await?;
@@ -311,11 +331,10 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?;
 
 					if args.library_sync {
-						write_storedkey_to_db(
-							&library.db,
-							&library.key_manager.access_keystore(uuid).await?,
-						)
-						.await?;
+						library
+							.key_store()
+							.put(&library.key_manager.access_keystore(uuid).await?)
+							.await?;
 
 						if args.automount {
 							library
@@ -383,9 +402,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						)
 						.await?;
 
-					for key in &updated_keys {
-						write_storedkey_to_db(&library.db, key).await?;
-					}
+					library.key_store().put_many(&updated_keys).await?;
 
 					invalidate_query!(library, "keys.list");
 					invalidate_query!(library, "keys.listMounted");
@@ -424,7 +441,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.await?;
 
 						// write the new verification key
-						write_storedkey_to_db(&library.db, &verification_key).await?;
+						library.key_store().put(&verification_key).await?;
 
 						Ok(())
 					},
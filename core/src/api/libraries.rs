@@ -1,6 +1,7 @@
 use crate::{
 	library::LibraryConfig,
 	prisma::statistics,
+	util::db::{db_stats, vacuum_library, DatabaseHealthCheck},
 	volume::{get_volumes, save_volume},
 };
 
@@ -86,6 +87,21 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					.await?)
 			})
 		})
+		.procedure("dbStats", {
+			R.with2(library())
+				.query(|(_, library), _: ()| async move { Ok(db_stats(&library.db).await?) })
+		})
+		.procedure("healthCheck", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(DatabaseHealthCheck::run(&library.db).await?)
+			})
+		})
+		.procedure("vacuum", {
+			R.with2(library())
+				.mutation(
+					|(_, library), _: ()| async move { Ok(vacuum_library(&library.db).await?) },
+				)
+		})
 		.procedure("create", {
 			#[derive(Deserialize, Type)]
 			pub struct CreateLibraryArgs {
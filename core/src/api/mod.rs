@@ -2,8 +2,11 @@ use rspc::{alpha::Rspc, Config};
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use std::sync::Arc;
+use uuid::Uuid;
 
-use crate::{node::NodeConfig, Node};
+use crate::{
+	library::KeyExpiryReport, location::indexer::IndexingJobReport, node::NodeConfig, Node,
+};
 
 use utils::{InvalidRequests, InvalidateOperationEvent};
 
@@ -16,8 +19,15 @@ pub type Router = rspc::Router<Ctx>;
 /// Represents an internal core event, these are exposed to client via a rspc subscription.
 #[derive(Debug, Clone, Serialize, Type)]
 pub enum CoreEvent {
-	NewThumbnail { cas_id: String },
+	NewThumbnail {
+		cas_id: String,
+	},
+	IndexingReport {
+		library_id: Uuid,
+		report: IndexingJobReport,
+	},
 	InvalidateOperation(InvalidateOperationEvent),
+	KeysExpiring(KeyExpiryReport),
 }
 
 mod categories;
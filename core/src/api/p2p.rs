@@ -5,7 +5,7 @@ use specta::Type;
 use std::path::PathBuf;
 use uuid::Uuid;
 
-use crate::p2p::P2PEvent;
+use crate::p2p::{P2PEvent, P2PManager};
 
 use super::{Ctx, R};
 
@@ -16,7 +16,12 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				let mut rx = ctx.p2p.subscribe();
 				async_stream::stream! {
 					// TODO: Don't block subscription start
+					let local = ctx.p2p.metadata_manager.get();
 					for peer in ctx.p2p.manager.get_discovered_peers().await {
+						for event in P2PManager::sync_candidates(&local, peer.peer_id, &peer.metadata) {
+							yield event;
+						}
+
 						yield P2PEvent::DiscoveredPeer {
 							peer_id: peer.peer_id,
 							metadata: peer.metadata,
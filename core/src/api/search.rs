@@ -12,8 +12,13 @@ use crate::{
 		locations::{file_path_with_object, object_with_file_paths, ExplorerItem},
 		utils::library,
 	},
+	invalidate_query,
 	library::Library,
 	location::{find_location, LocationError},
+	object::virtual_directory::{
+		list_saved_virtual_directories, list_virtual_directory, save_virtual_directory,
+		VirtualDirectory,
+	},
 	prisma::{self, file_path, object, tag, tag_on_object},
 	util::db::chain_optional_iter,
 };
@@ -34,7 +39,7 @@ struct OptionalRange<T> {
 }
 
 #[derive(Deserialize, Type, Debug, Clone, Copy)]
-enum SortOrder {
+pub(super) enum SortOrder {
 	Asc,
 	Desc,
 }
@@ -411,4 +416,43 @@ pub fn mount() -> AlphaRouter<Ctx> {
 				},
 			)
 		})
+		.procedure("virtualDirectory", {
+			#[derive(Type, Deserialize)]
+			pub struct VirtualDirectoryArgs {
+				pub directory: VirtualDirectory,
+				pub skip: Option<u32>,
+				pub take: Option<u32>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: VirtualDirectoryArgs| async move {
+					Ok(list_virtual_directory(
+						&library.db,
+						&args.directory,
+						args.skip.unwrap_or(0) as usize,
+						args.take.unwrap_or(100) as usize,
+					)
+					.await?)
+				})
+		})
+		.procedure("savedVirtualDirectories", {
+			R.with2(library()).query(|(_, library), _: ()| async move {
+				Ok(list_saved_virtual_directories(&library.db).await?)
+			})
+		})
+		.procedure("saveVirtualDirectory", {
+			#[derive(Type, Deserialize)]
+			pub struct SaveVirtualDirectoryArgs {
+				pub name: String,
+				pub directory: VirtualDirectory,
+			}
+
+			R.with2(library())
+				.mutation(|(_, library), args: SaveVirtualDirectoryArgs| async move {
+					save_virtual_directory(&library.db, args.name, &args.directory).await?;
+
+					invalidate_query!(library, "search.savedVirtualDirectories");
+					Ok(())
+				})
+		})
 }
@@ -34,6 +34,15 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?)
 				})
 		})
+		.procedure("getEffectiveForObject", {
+			R.with2(library())
+				.query(|(_, library), object_id: Uuid| async move {
+					Ok(library
+						.tag_inheritance
+						.effective_tags(&library.db, object_id)
+						.await?)
+				})
+		})
 		.procedure("get", {
 			R.with2(library())
 				.query(|(_, library), tag_id: i32| async move {
@@ -128,7 +137,13 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.await?;
 					}
 
+					library
+						.tag_inheritance
+						.invalidate_object(args.object_id)
+						.await;
+
 					invalidate_query!(library, "tags.getForObject");
+					invalidate_query!(library, "tags.getEffectiveForObject");
 
 					Ok(())
 				})
@@ -183,6 +198,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					)
 					.await?;
 
+					library.tag_inheritance.invalidate_all().await;
+
 					invalidate_query!(library, "tags.list");
 
 					Ok(())
@@ -199,6 +216,8 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.exec()
 						.await?;
 
+					library.tag_inheritance.invalidate_all().await;
+
 					invalidate_query!(library, "tags.list");
 
 					Ok(())
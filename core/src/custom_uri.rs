@@ -119,6 +119,8 @@ async fn handle_thumbnail(
 		}
 	})?;
 
+	node.thumbnail_cache.mark_accessed(file_cas_id).await;
+
 	let content_lenght = file
 		.metadata()
 		.await
@@ -72,6 +72,8 @@ pub enum JobError {
 	MatchingSrcDest(PathBuf),
 	#[error("action would overwrite another file: {}", .0.display())]
 	WouldOverwrite(PathBuf),
+	#[error("can't overwrite a directory with a file, or a file with a directory: {}", .0.display())]
+	MismatchedOverwrite(PathBuf),
 	#[error("item of type '{0}' with id '{1}' is missing from the db")]
 	MissingFromDb(&'static str, String),
 	#[error("the cas id is not set on the path data")]
@@ -6,6 +6,9 @@ use crate::{
 	library::LibraryManager,
 	location::{LocationManager, LocationManagerError},
 	node::NodeConfigManager,
+	object::preview::{
+		ThumbnailCache, DEFAULT_THUMBNAIL_CACHE_MAX_BYTES, THUMBNAIL_CACHE_DIR_NAME,
+	},
 	p2p::P2PManager,
 };
 
@@ -43,6 +46,7 @@ pub struct NodeContext {
 	pub location_manager: Arc<LocationManager>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
 	pub p2p: Arc<P2PManager>,
+	pub thumbnail_cache: Arc<ThumbnailCache>,
 }
 
 pub struct Node {
@@ -52,6 +56,7 @@ pub struct Node {
 	location_manager: Arc<LocationManager>,
 	jobs: Arc<JobManager>,
 	p2p: Arc<P2PManager>,
+	pub(crate) thumbnail_cache: Arc<ThumbnailCache>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 	// peer_request: tokio::sync::Mutex<Option<PeerRequest>>,
 }
@@ -74,6 +79,13 @@ impl Node {
 		let jobs = JobManager::new();
 		let location_manager = LocationManager::new();
 		let (p2p, mut p2p_rx) = P2PManager::new(config.clone()).await?;
+		let thumbnail_cache = Arc::new(
+			ThumbnailCache::load(
+				data_dir.join(THUMBNAIL_CACHE_DIR_NAME),
+				DEFAULT_THUMBNAIL_CACHE_MAX_BYTES,
+			)
+			.await?,
+		);
 
 		let library_manager = LibraryManager::new(
 			data_dir.join("libraries"),
@@ -83,6 +95,7 @@ impl Node {
 				location_manager: location_manager.clone(),
 				p2p: p2p.clone(),
 				event_bus_tx: event_bus.0.clone(),
+				thumbnail_cache: thumbnail_cache.clone(),
 			},
 		)
 		.await?;
@@ -124,6 +137,7 @@ impl Node {
 			location_manager,
 			jobs,
 			p2p,
+			thumbnail_cache,
 			event_bus,
 			// peer_request: tokio::sync::Mutex::new(None),
 		};
@@ -233,6 +247,8 @@ pub enum NodeError {
 	LocationManager(#[from] LocationManagerError),
 	#[error("failed to initialize p2p manager: {0}")]
 	P2PManager(#[from] sd_p2p::ManagerError),
+	#[error("failed to initialize thumbnail cache: {0}")]
+	ThumbnailCache(#[from] object::preview::ThumbnailerError),
 	#[error("invalid platform integer")]
 	InvalidPlatformInt(i32),
 	#[cfg(debug_assertions)]
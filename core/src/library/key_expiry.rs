@@ -0,0 +1,87 @@
+use crate::{api::CoreEvent, prisma::PrismaClient};
+
+use std::{
+	sync::{Arc, Weak},
+	time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use specta::Type;
+use tokio::{sync::broadcast, time::interval};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use super::list_expiring_keys;
+
+/// How long before a key expires the frontend starts being notified about it
+pub const KEY_EXPIRY_WARNING: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How often the keys of a library are checked for expiry
+const KEY_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// KeyExpiryReport is emitted as a [`CoreEvent`] when some of the keys of a library have to be rotated soon, or already had to be.
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct KeyExpiryReport {
+	pub library_id: Uuid,
+	pub keys: Vec<ExpiringKey>,
+}
+
+/// ExpiringKey is a key that expires within [`KEY_EXPIRY_WARNING`], or already has.
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct ExpiringKey {
+	pub uuid: Uuid,
+	pub expires_at: DateTime<Utc>,
+}
+
+/// This checks the keys of a library for expiry every hour, emitting a [`KeyExpiryReport`] whenever any of them expire within [`KEY_EXPIRY_WARNING`].
+///
+/// The checker only holds onto the database weakly, so it stops once the library is unloaded.
+pub(super) fn spawn_key_expiry_checker(
+	library_id: Uuid,
+	db: &Arc<PrismaClient>,
+	event_bus_tx: broadcast::Sender<CoreEvent>,
+) {
+	let db = Arc::downgrade(db);
+
+	tokio::spawn(async move {
+		let mut interval = interval(KEY_EXPIRY_CHECK_INTERVAL);
+
+		loop {
+			interval.tick().await;
+
+			let Some(db) = Weak::upgrade(&db) else {
+				break;
+			};
+
+			let keys = match list_expiring_keys(&db, KEY_EXPIRY_WARNING).await {
+				Ok(keys) => keys,
+				Err(e) => {
+					error!("Failed to check the keys of library '{library_id}' for expiry: {e:#?}");
+					continue;
+				}
+			};
+
+			let keys = keys
+				.into_iter()
+				.filter_map(|key| {
+					key.expires_at.map(|expires_at| ExpiringKey {
+						uuid: key.uuid,
+						expires_at,
+					})
+				})
+				.collect::<Vec<_>>();
+
+			if keys.is_empty() {
+				continue;
+			}
+
+			if let Err(e) = event_bus_tx.send(CoreEvent::KeysExpiring(KeyExpiryReport {
+				library_id,
+				keys,
+			})) {
+				warn!("Error sending event to event bus: {e:?}");
+			}
+		}
+	});
+}
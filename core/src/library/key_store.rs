@@ -0,0 +1,112 @@
+use crate::prisma::{key, PrismaClient};
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{BoxStream, StreamExt};
+use sd_crypto::keys::keymanager::StoredKey;
+use uuid::Uuid;
+
+use super::{
+	active_key, delete_storedkey_from_db, key_exists, read_storedkey_from_db, storedkey_from_row,
+	stream_storedkeys, write_storedkey_to_db, write_storedkeys_to_db, LibraryManagerError,
+};
+
+/// KeyStore is where a library persists the `StoredKey`s that aren't memory-only.
+///
+/// This keeps the key management code independent of how (and where) the keys are actually stored.
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+	/// put writes a key, replacing the key material of any existing key with the same UUID
+	async fn put(&self, key: &StoredKey) -> Result<(), LibraryManagerError>;
+
+	/// put_many writes several keys at once, either all of them or none, and returns how many were written
+	async fn put_many(&self, keys: &[StoredKey]) -> Result<usize, LibraryManagerError>;
+
+	/// get reads a single key, returning `LibraryManagerError::KeyNotFound` if it doesn't exist
+	async fn get(&self, uuid: Uuid) -> Result<StoredKey, LibraryManagerError>;
+
+	/// exists checks whether a key is in the store, without reading it
+	async fn exists(&self, uuid: Uuid) -> Result<bool, LibraryManagerError>;
+
+	/// list reads every key in the store
+	async fn list(&self) -> Result<Vec<StoredKey>, LibraryManagerError>;
+
+	/// stream reads every key in the store lazily, so callers looking for a specific key can stop early
+	fn stream(&self) -> BoxStream<'_, Result<StoredKey, LibraryManagerError>>;
+
+	/// set_expiry changes when a key expires (or clears it), returning `LibraryManagerError::KeyNotFound` if it doesn't exist
+	async fn set_expiry(
+		&self,
+		uuid: Uuid,
+		expires_at: Option<DateTime<Utc>>,
+	) -> Result<(), LibraryManagerError>;
+
+	/// remove deletes a key, returning `LibraryManagerError::KeyNotFound` if it doesn't exist
+	async fn remove(&self, uuid: Uuid) -> Result<(), LibraryManagerError>;
+}
+
+/// PrismaKeyStore is a [`KeyStore`] backed by the `key` table of a library's database.
+#[derive(Clone)]
+pub struct PrismaKeyStore(pub Arc<PrismaClient>);
+
+#[async_trait]
+impl KeyStore for PrismaKeyStore {
+	async fn put(&self, key: &StoredKey) -> Result<(), LibraryManagerError> {
+		write_storedkey_to_db(&self.0, key).await.map(|_| ())
+	}
+
+	async fn put_many(&self, keys: &[StoredKey]) -> Result<usize, LibraryManagerError> {
+		write_storedkeys_to_db(&self.0, keys).await
+	}
+
+	async fn get(&self, uuid: Uuid) -> Result<StoredKey, LibraryManagerError> {
+		read_storedkey_from_db(&self.0, uuid).await
+	}
+
+	async fn exists(&self, uuid: Uuid) -> Result<bool, LibraryManagerError> {
+		key_exists(&self.0, uuid).await
+	}
+
+	async fn list(&self) -> Result<Vec<StoredKey>, LibraryManagerError> {
+		self.0
+			.key()
+			.find_many(vec![active_key()])
+			.exec()
+			.await?
+			.into_iter()
+			.map(storedkey_from_row)
+			.collect()
+	}
+
+	fn stream(&self) -> BoxStream<'_, Result<StoredKey, LibraryManagerError>> {
+		stream_storedkeys(&self.0).boxed()
+	}
+
+	async fn set_expiry(
+		&self,
+		uuid: Uuid,
+		expires_at: Option<DateTime<Utc>>,
+	) -> Result<(), LibraryManagerError> {
+		let updated = self
+			.0
+			.key()
+			.update_many(
+				vec![key::uuid::equals(uuid.to_string()), active_key()],
+				vec![key::expires_at::set(expires_at.map(Into::into))],
+			)
+			.exec()
+			.await?;
+
+		if updated == 0 {
+			return Err(LibraryManagerError::KeyNotFound(uuid));
+		}
+
+		Ok(())
+	}
+
+	async fn remove(&self, uuid: Uuid) -> Result<(), LibraryManagerError> {
+		delete_storedkey_from_db(&self.0, uuid).await
+	}
+}
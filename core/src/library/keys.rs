@@ -0,0 +1,1954 @@
+use crate::{
+	prisma::{file_path, key, object, PrismaClient, SortOrder},
+	util::{
+		audit::{emit_audit_event, AuditEvent, SkipReason},
+		db::{chain_optional_iter, parse_uuid, with_retry_transaction},
+		error::FileIOError,
+	},
+};
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use sd_crypto::{
+	crypto::{Decryptor, Encryptor},
+	keys::keymanager::{migrate_storedkey, StoredKey, StoredKeyVersion},
+	primitives::{to_array, ENCRYPTED_KEY_LEN, LATEST_STORED_KEY, SALT_LEN},
+	types::{Algorithm, HashingAlgorithm, Key, Nonce, Params, Salt},
+	Protected,
+};
+use thiserror::Error;
+use tokio::fs;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::LibraryManagerError;
+
+/// This writes a `StoredKey` to prisma, returning the id of its row
+/// If the key is marked as memory-only, it is skipped and `None` is returned
+///
+/// If a key with the same UUID already exists, its key material is updated instead (and its existing id is returned)
+///
+/// Every column is either encrypted (`master_key` and `key`) or not secret (nonces, salts and metadata),
+/// so no plaintext key material passes through here and the temporary buffers aren't zeroized.
+/// They're moved into the prisma query, so they couldn't be zeroized after it runs anyway.
+pub async fn write_storedkey_to_db(
+	db: &PrismaClient,
+	key: &StoredKey,
+) -> Result<Option<i32>, LibraryManagerError> {
+	if key.memory_only {
+		emit_audit_event(AuditEvent::KeySkipped {
+			uuid: key.uuid,
+			reason: SkipReason::MemoryOnly,
+		});
+		return Ok(None);
+	}
+
+	validate_storedkey(key)?;
+
+	let version = key_json("version", serde_json::to_string(&key.version))?;
+	let key_type = key_json("key_type", serde_json::to_string(&key.key_type))?;
+	let algorithm = key_json("algorithm", serde_json::to_string(&key.algorithm))?;
+	let hashing_algorithm = key_json(
+		"hashing_algorithm",
+		serde_json::to_string(&key.hashing_algorithm),
+	)?;
+
+	let checksum = storedkey_checksum(key);
+
+	let row = db
+		.key()
+		.upsert(
+			key::uuid::equals(key.uuid.to_string()),
+			key::create(
+				key.uuid.to_string(),
+				version.clone(),
+				key_type.clone(),
+				algorithm.clone(),
+				hashing_algorithm.clone(),
+				key.content_salt.0.to_vec(),
+				key.master_key.to_vec(),
+				key.master_key_nonce.to_vec(),
+				key.key_nonce.to_vec(),
+				key.key.to_vec(),
+				key.salt.to_vec(),
+				vec![
+					key::checksum::set(Some(checksum.clone())),
+					key::expires_at::set(key.expires_at.map(Into::into)),
+				],
+			),
+			vec![
+				key::version::set(version),
+				key::key_type::set(key_type),
+				key::algorithm::set(algorithm),
+				key::hashing_algorithm::set(hashing_algorithm),
+				key::content_salt::set(key.content_salt.0.to_vec()),
+				key::master_key::set(key.master_key.to_vec()),
+				key::master_key_nonce::set(key.master_key_nonce.to_vec()),
+				key::key_nonce::set(key.key_nonce.to_vec()),
+				key::key::set(key.key.to_vec()),
+				key::salt::set(key.salt.to_vec()),
+				key::checksum::set(Some(checksum)),
+				key::expires_at::set(key.expires_at.map(Into::into)),
+				// writing a soft-deleted key brings it back
+				key::deleted_at::set(None),
+			],
+		)
+		.select(key::select!({ id }))
+		.exec()
+		.await
+		// another write of the same key can still win the race between the upsert's lookup and its insert
+		.map_err(|e| LibraryManagerError::from(e).for_key(key.uuid))?;
+
+	Ok(Some(row.id))
+}
+
+/// This computes the BLAKE3 checksum of a `StoredKey`'s key material, which is stored alongside it to detect corruption
+fn storedkey_checksum(key: &StoredKey) -> Vec<u8> {
+	let mut hasher = blake3::Hasher::new();
+
+	hasher.update(&key.master_key);
+	hasher.update(&key.master_key_nonce);
+	hasher.update(&key.key_nonce);
+	hasher.update(&key.key);
+	hasher.update(&key.content_salt);
+	hasher.update(&key.salt);
+
+	hasher.finalize().as_bytes().to_vec()
+}
+
+/// This writes multiple `StoredKey`s to prisma inside of a single transaction
+///
+/// Either every persistable key is written, or none of them are. Memory-only keys are skipped,
+/// and the amount of keys that were actually written is returned. No key is written if two different keys
+/// would share a content salt, whether both are in `keys` or one of them is already in prisma.
+pub async fn write_storedkeys_to_db(
+	db: &PrismaClient,
+	keys: &[StoredKey],
+) -> Result<usize, LibraryManagerError> {
+	let (memory_only, keys): (Vec<_>, Vec<_>) = keys.iter().partition(|k| k.memory_only);
+
+	for key in memory_only {
+		emit_audit_event(AuditEvent::KeySkipped {
+			uuid: key.uuid,
+			reason: SkipReason::MemoryOnly,
+		});
+	}
+
+	if keys.is_empty() {
+		return Ok(0);
+	}
+
+	ensure_unique_content_salts(&keys)?;
+
+	let keys = &keys;
+	with_retry_transaction(db, |tx| async move {
+		ensure_content_salts_are_unused(&tx, keys).await?;
+
+		for key in keys {
+			write_storedkey_to_db(&tx, key).await?;
+		}
+
+		Ok(keys.len())
+	})
+	.await
+}
+
+/// This checks that no two different keys (by uuid) have the same content salt, returning the first key that reuses one
+fn ensure_unique_content_salts(keys: &[&StoredKey]) -> Result<(), LibraryManagerError> {
+	let mut salts = HashMap::with_capacity(keys.len());
+
+	for key in keys {
+		match salts.insert(key.content_salt.0, key.uuid) {
+			Some(uuid) if uuid != key.uuid => {
+				return Err(LibraryManagerError::DuplicateContentSalt { uuid: key.uuid })
+			}
+			_ => {}
+		}
+	}
+
+	Ok(())
+}
+
+/// This checks that none of the content salts are used by a key in prisma (soft-deleted ones included) other than the ones being written
+async fn ensure_content_salts_are_unused(
+	db: &PrismaClient,
+	keys: &[&StoredKey],
+) -> Result<(), LibraryManagerError> {
+	let taken = db
+		.key()
+		.find_many(vec![
+			key::content_salt::in_vec(keys.iter().map(|k| k.content_salt.0.to_vec()).collect()),
+			key::uuid::not_in_vec(keys.iter().map(|k| k.uuid.to_string()).collect()),
+		])
+		.select(key::select!({ content_salt }))
+		.exec()
+		.await?;
+
+	match keys
+		.iter()
+		.find(|k| taken.iter().any(|row| row.content_salt == k.content_salt.0))
+	{
+		Some(key) => Err(LibraryManagerError::DuplicateContentSalt { uuid: key.uuid }),
+		None => Ok(()),
+	}
+}
+
+/// This persists the `master_key` and `master_key_nonce` of keys that the key manager has re-encrypted, e.g. after a password change
+///
+/// All keys are updated in a single transaction, so if any of them fails (including because it's not in prisma) none of them are.
+/// Memory-only keys are skipped.
+pub async fn reencrypt_master_keys(
+	db: &PrismaClient,
+	keys: &mut [StoredKey],
+) -> Result<(), LibraryManagerError> {
+	let keys = keys.iter().filter(|k| !k.memory_only).collect::<Vec<_>>();
+
+	if keys.is_empty() {
+		return Ok(());
+	}
+
+	for key in &keys {
+		validate_storedkey(key)?;
+	}
+
+	db._transaction()
+		.run(|tx| async move {
+			for key in keys {
+				let updated = tx
+					.key()
+					.update_many(
+						vec![key::uuid::equals(key.uuid.to_string())],
+						vec![
+							key::master_key::set(key.master_key.to_vec()),
+							key::master_key_nonce::set(key.master_key_nonce.to_vec()),
+							key::checksum::set(Some(storedkey_checksum(key))),
+						],
+					)
+					.exec()
+					.await?;
+
+				if updated == 0 {
+					return Err(LibraryManagerError::KeyNotFound(key.uuid));
+				}
+			}
+
+			Ok(())
+		})
+		.await
+}
+
+/// This deletes a `StoredKey` from prisma, using its UUID
+///
+/// This returns `LibraryManagerError::KeyNotFound` if there was no such key, which is always the case for memory-only keys
+pub async fn delete_storedkey_from_db(
+	db: &PrismaClient,
+	uuid: Uuid,
+) -> Result<(), LibraryManagerError> {
+	let deleted = db
+		.key()
+		.delete_many(vec![key::uuid::equals(uuid.to_string())])
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(LibraryManagerError::KeyNotFound(uuid));
+	}
+
+	Ok(())
+}
+
+/// This soft-deletes a `StoredKey` in prisma, using its UUID
+///
+/// The row is kept for auditing, but the key is left out of all reads unless they ask for deleted keys.
+/// It's removed for good by [`purge_deleted_keys`]. This returns `LibraryManagerError::KeyNotFound` if there was no such (active) key.
+pub async fn soft_delete_storedkey(
+	db: &PrismaClient,
+	uuid: Uuid,
+) -> Result<(), LibraryManagerError> {
+	let deleted = db
+		.key()
+		.update_many(
+			vec![key::uuid::equals(uuid.to_string()), active_key()],
+			vec![key::deleted_at::set(Some(Utc::now().into()))],
+		)
+		.exec()
+		.await?;
+
+	if deleted == 0 {
+		return Err(LibraryManagerError::KeyNotFound(uuid));
+	}
+
+	Ok(())
+}
+
+/// This hard-deletes the keys that were soft-deleted more than `older_than` ago, returning how many were removed
+pub async fn purge_deleted_keys(
+	db: &PrismaClient,
+	older_than: Duration,
+) -> Result<usize, LibraryManagerError> {
+	// nothing can have been deleted before the earliest representable date
+	let Some(cutoff) = chrono::Duration::from_std(older_than)
+		.ok()
+		.and_then(|older_than| Utc::now().checked_sub_signed(older_than))
+	else {
+		return Ok(0);
+	};
+
+	Ok(db
+		.key()
+		.delete_many(vec![key::deleted_at::lt(cutoff.into())])
+		.exec()
+		.await? as usize)
+}
+
+/// This marks a `StoredKey` as a version of the logical key `family_id`, so that [`purge_rotated_keys`] can tell the versions of a rotated key apart
+pub async fn set_key_family(
+	db: &PrismaClient,
+	uuid: Uuid,
+	family_id: Uuid,
+) -> Result<(), LibraryManagerError> {
+	let updated = db
+		.key()
+		.update_many(
+			vec![key::uuid::equals(uuid.to_string())],
+			vec![key::key_family_id::set(Some(family_id.to_string()))],
+		)
+		.exec()
+		.await?;
+
+	if updated == 0 {
+		return Err(LibraryManagerError::KeyNotFound(uuid));
+	}
+
+	Ok(())
+}
+
+/// This sets the name that the user gave a `StoredKey`, using its UUID
+///
+/// The name is its own column rather than part of the serialized key, so only it is updated
+/// and anything else that's being written to the row concurrently isn't clobbered.
+pub async fn rename_key(
+	db: &PrismaClient,
+	uuid: Uuid,
+	new_name: String,
+) -> Result<(), LibraryManagerError> {
+	let updated = db
+		.key()
+		.update_many(
+			vec![key::uuid::equals(uuid.to_string()), active_key()],
+			vec![key::name::set(Some(new_name))],
+		)
+		.exec()
+		.await?;
+
+	if updated == 0 {
+		return Err(LibraryManagerError::KeyNotFound(uuid));
+	}
+
+	Ok(())
+}
+
+/// This hard-deletes the versions of rotated keys that have been superseded, keeping the `retain_last_n` most recent versions of each key family
+///
+/// Versions are ordered by when they were inserted. Keys without a `key_family_id` were never rotated, so they're always kept.
+/// Returns how many rows were deleted.
+pub async fn purge_rotated_keys(
+	db: &PrismaClient,
+	retain_last_n: usize,
+) -> Result<u64, LibraryManagerError> {
+	with_retry_transaction(db, |tx| async move {
+		let versions = tx
+			.key()
+			.find_many(vec![key::key_family_id::not(None)])
+			.order_by(key::id::order(SortOrder::Desc))
+			.select(key::select!({ id key_family_id }))
+			.exec()
+			.await?;
+
+		let mut seen = HashMap::<_, usize>::new();
+		let superseded = versions
+			.into_iter()
+			.filter(|version| {
+				let count = seen.entry(version.key_family_id.clone()).or_default();
+				*count += 1;
+				*count > retain_last_n
+			})
+			.map(|version| version.id)
+			.collect::<Vec<_>>();
+
+		if superseded.is_empty() {
+			return Ok(0);
+		}
+
+		Ok(tx
+			.key()
+			.delete_many(vec![key::id::in_vec(superseded)])
+			.exec()
+			.await? as u64)
+	})
+	.await
+}
+
+/// Filters out soft-deleted keys
+pub(crate) fn active_key() -> key::WhereParam {
+	key::deleted_at::equals(None)
+}
+
+/// This reads a `StoredKey` from prisma, using its UUID
+///
+/// Soft-deleted keys are reported as `LibraryManagerError::KeyNotFound`
+pub async fn read_storedkey_from_db(
+	db: &PrismaClient,
+	uuid: Uuid,
+) -> Result<StoredKey, LibraryManagerError> {
+	db.key()
+		.find_first(vec![key::uuid::equals(uuid.to_string()), active_key()])
+		.exec()
+		.await?
+		.ok_or(LibraryManagerError::KeyNotFound(uuid))
+		.and_then(storedkey_from_row)
+}
+
+/// StoredKeysRead holds the result of [`read_all_storedkeys_from_db`].
+#[derive(Clone, Default)]
+pub struct StoredKeysRead {
+	/// The keys that were successfully read
+	pub keys: Vec<StoredKey>,
+	/// How many rows were skipped because they couldn't be turned back into a `StoredKey`
+	pub skipped: usize,
+}
+
+/// This reads every `StoredKey` from prisma, including soft-deleted ones if `include_deleted` is set
+///
+/// Rows that fail to deserialize are logged and skipped, so that one corrupt key can't prevent a library from loading
+pub async fn read_all_storedkeys_from_db(
+	db: &PrismaClient,
+	include_deleted: bool,
+) -> Result<StoredKeysRead, LibraryManagerError> {
+	let mut read = StoredKeysRead::default();
+
+	let filter = chain_optional_iter([], [(!include_deleted).then(active_key)]);
+
+	for row in db.key().find_many(filter).exec().await? {
+		let uuid = row.uuid.clone();
+
+		match storedkey_from_row(row) {
+			Ok(key) => read.keys.push(key),
+			Err(e) => {
+				warn!("Skipping stored key '{uuid}' as it could not be read: {e}");
+				read.skipped += 1;
+			}
+		}
+	}
+
+	Ok(read)
+}
+
+/// How many keys [`stream_storedkeys`] fetches from prisma at a time
+const STOREDKEY_PAGE_SIZE: i64 = 200;
+
+/// This streams every `StoredKey` from prisma, fetching them a page at a time
+///
+/// Keys are yielded in the order they were added. Dropping the stream stops any further pages from being fetched.
+pub fn stream_storedkeys(
+	db: &PrismaClient,
+) -> impl Stream<Item = Result<StoredKey, LibraryManagerError>> + Send + '_ {
+	async_stream::try_stream! {
+		let mut cursor = None;
+
+		loop {
+			// one extra row is fetched, which becomes the (inclusive) cursor of the next page
+			let mut query = db
+				.key()
+				.find_many(vec![active_key()])
+				.take(STOREDKEY_PAGE_SIZE + 1)
+				.order_by(key::id::order(SortOrder::Asc));
+
+			if let Some(id) = cursor {
+				query = query.cursor(key::id::equals(id));
+			}
+
+			let mut rows = query.exec().await?;
+
+			cursor = (rows.len() as i64 > STOREDKEY_PAGE_SIZE)
+				.then(|| rows.pop())
+				.flatten()
+				.map(|row| row.id);
+
+			for row in rows {
+				yield storedkey_from_row(row)?;
+			}
+
+			if cursor.is_none() {
+				break;
+			}
+		}
+	}
+}
+
+/// The most keys [`list_storedkeys_paginated`] returns at once
+pub const MAX_STOREDKEY_PAGE_LIMIT: i64 = 500;
+
+/// This lists a page of `limit` `StoredKey`s from prisma, starting `offset` keys in
+///
+/// Keys are listed in the order they were added, and only the requested page is read from the database.
+/// An offset past the last key returns an empty page. `limit` must be between 1 and [`MAX_STOREDKEY_PAGE_LIMIT`].
+pub async fn list_storedkeys_paginated(
+	db: &PrismaClient,
+	offset: i64,
+	limit: i64,
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	if offset < 0 || !(1..=MAX_STOREDKEY_PAGE_LIMIT).contains(&limit) {
+		return Err(LibraryManagerError::InvalidPagination { offset, limit });
+	}
+
+	db.key()
+		.find_many(vec![active_key()])
+		.order_by(key::id::order(SortOrder::Asc))
+		.skip(offset)
+		.take(limit)
+		.exec()
+		.await?
+		.into_iter()
+		.map(storedkey_from_row)
+		.collect()
+}
+
+/// DuplicateKeyGroup is a set of `key` rows that share the same UUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKeyGroup {
+	pub uuid: Uuid,
+	/// The ids of the conflicting rows, oldest first
+	pub ids: Vec<i32>,
+}
+
+/// DeduplicationStrategy decides what [`deduplicate_keys`] does with duplicated keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeduplicationStrategy {
+	/// Keep the most recently inserted row of each group and delete the rest
+	KeepNewest,
+	/// Leave the rows alone and return `LibraryManagerError::DuplicateKeys`
+	Error,
+}
+
+/// This finds keys that appear in prisma more than once
+///
+/// The `uuid` column is unique, so this can only happen with databases that were restored without their indexes.
+pub async fn detect_duplicate_keys(
+	db: &PrismaClient,
+) -> Result<Vec<DuplicateKeyGroup>, LibraryManagerError> {
+	db._transaction()
+		.run(|tx| async move { duplicate_key_groups(&tx).await })
+		.await
+}
+
+/// This removes duplicated keys from prisma according to `strategy`, returning how many rows were deleted
+///
+/// Objects and file paths that point at a deleted row are moved over to the row that's kept.
+pub async fn deduplicate_keys(
+	db: &PrismaClient,
+	strategy: DeduplicationStrategy,
+) -> Result<usize, LibraryManagerError> {
+	db._transaction()
+		.run(|tx| async move {
+			let groups = duplicate_key_groups(&tx).await?;
+
+			if strategy == DeduplicationStrategy::Error && !groups.is_empty() {
+				return Err(LibraryManagerError::DuplicateKeys(
+					groups.into_iter().map(|group| group.uuid).collect(),
+				));
+			}
+
+			let mut deleted = 0;
+
+			for group in groups {
+				let Some((&kept, stale)) = group.ids.split_last() else {
+					continue;
+				};
+				let stale = stale.to_vec();
+
+				tx.file_path()
+					.update_many(
+						vec![file_path::key_id::in_vec(stale.clone())],
+						vec![file_path::key_id::set(Some(kept))],
+					)
+					.exec()
+					.await?;
+				tx.object()
+					.update_many(
+						vec![object::key_id::in_vec(stale.clone())],
+						vec![object::key_id::set(Some(kept))],
+					)
+					.exec()
+					.await?;
+
+				deleted += tx
+					.key()
+					.delete_many(vec![key::id::in_vec(stale)])
+					.exec()
+					.await? as usize;
+			}
+
+			Ok(deleted)
+		})
+		.await
+}
+
+async fn duplicate_key_groups(
+	db: &PrismaClient,
+) -> Result<Vec<DuplicateKeyGroup>, LibraryManagerError> {
+	let mut groups = HashMap::<String, Vec<i32>>::new();
+
+	for row in db
+		.key()
+		.find_many(vec![])
+		.order_by(key::id::order(SortOrder::Asc))
+		.exec()
+		.await?
+	{
+		groups.entry(row.uuid).or_default().push(row.id);
+	}
+
+	let mut duplicates = groups
+		.into_iter()
+		.filter(|(_, ids)| ids.len() > 1)
+		.map(|(uuid, ids)| {
+			Ok(DuplicateKeyGroup {
+				uuid: parse_uuid(&uuid)?,
+				ids,
+			})
+		})
+		.collect::<Result<Vec<_>, LibraryManagerError>>()?;
+
+	duplicates.sort_by_key(|group| group.ids[0]);
+
+	Ok(duplicates)
+}
+
+/// This lists every `StoredKey` in prisma that's encrypted with `algorithm`
+///
+/// The algorithm is stored as serialized JSON, so the keys are filtered after they're deserialized
+pub async fn list_storedkeys_by_algorithm(
+	db: &PrismaClient,
+	algorithm: &Algorithm,
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	db.key()
+		.find_many(vec![active_key()])
+		.exec()
+		.await?
+		.into_iter()
+		.map(storedkey_from_row)
+		.filter(|key| !matches!(key, Ok(key) if key.algorithm != *algorithm))
+		.collect()
+}
+
+/// This lists the UUIDs of the keys in prisma that were stored with an older `StoredKeyVersion`, and need to go through `migrate_storedkey`
+///
+/// Only the `version` column is compared, so keys are listed even if their version can no longer be deserialized.
+pub async fn list_storedkeys_needing_migration(
+	db: &PrismaClient,
+) -> Result<Vec<Uuid>, LibraryManagerError> {
+	let latest = serde_json::to_string(&LATEST_STORED_KEY)?;
+
+	db.key()
+		.find_many(vec![active_key(), key::version::not(latest)])
+		.order_by(key::id::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| Ok(parse_uuid(&row.uuid)?))
+		.collect()
+}
+
+/// This lists every `StoredKey` in prisma that's past its `expires_at`, and has to be rotated before it can encrypt anything again
+pub async fn list_expired_keys(db: &PrismaClient) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	list_keys_expiring_before(db, Utc::now()).await
+}
+
+/// This lists every `StoredKey` in prisma that expires within `within` from now, including the ones that already have
+pub async fn list_expiring_keys(
+	db: &PrismaClient,
+	within: Duration,
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	// every key expires before the latest representable date
+	let cutoff = chrono::Duration::from_std(within)
+		.ok()
+		.and_then(|within| Utc::now().checked_add_signed(within))
+		.unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+	list_keys_expiring_before(db, cutoff).await
+}
+
+async fn list_keys_expiring_before(
+	db: &PrismaClient,
+	cutoff: DateTime<Utc>,
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	db.key()
+		.find_many(vec![active_key(), key::expires_at::lte(cutoff.into())])
+		.order_by(key::expires_at::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(storedkey_from_row)
+		.collect()
+}
+
+/// This counts the `StoredKey`s in prisma without reading them back
+///
+/// Memory-only keys are never written to the database and soft-deleted keys are left out, so this is the number of keys the library would load
+pub async fn count_storedkeys(db: &PrismaClient) -> Result<i64, LibraryManagerError> {
+	Ok(db.key().count(vec![active_key()]).exec().await?)
+}
+
+/// This checks whether a `StoredKey` with the given UUID is in prisma, without reading (or decrypting) any of it
+///
+/// Soft-deleted keys don't count, the same as for [`read_storedkey_from_db`].
+pub async fn key_exists(db: &PrismaClient, uuid: Uuid) -> Result<bool, LibraryManagerError> {
+	Ok(db
+		.key()
+		.count(vec![key::uuid::equals(uuid.to_string()), active_key()])
+		.exec()
+		.await?
+		> 0)
+}
+
+/// The same as [`key_exists`], but soft-deleted keys count too, as writing the key would bring them back
+async fn key_row_exists(db: &PrismaClient, uuid: Uuid) -> Result<bool, LibraryManagerError> {
+	Ok(db
+		.key()
+		.count(vec![key::uuid::equals(uuid.to_string())])
+		.exec()
+		.await?
+		> 0)
+}
+
+/// Identifies a key store backup created by [`export_keystore`], and is authenticated alongside it
+const KEYSTORE_BACKUP_MAGIC: &[u8; 8] = b"sdkeybk1";
+const KEYSTORE_BACKUP_ALGORITHM: Algorithm = Algorithm::Aes256Gcm;
+const KEYSTORE_BACKUP_HASHING_ALGORITHM: HashingAlgorithm =
+	HashingAlgorithm::Argon2id(Params::Standard);
+
+/// Identifies a keyring file created by [`export_keyring`]. It's followed by a single version byte, and both are authenticated alongside the keys
+const KEYRING_MAGIC: &[u8; 8] = b"sdkeyrng";
+const KEYRING_VERSION: u8 = 1;
+
+/// This exports every `StoredKey` in prisma as a single encrypted blob, so they can be moved to another device
+///
+/// The keys are serialized as JSON and encrypted with AES-256-GCM, using a key derived from `passphrase` with Argon2id.
+/// The blob is laid out as `magic | salt | nonce | ciphertext`.
+pub async fn export_keystore(
+	db: &PrismaClient,
+	passphrase: &str,
+) -> Result<Vec<u8>, LibraryManagerError> {
+	let keys = read_storedkeys_for_export(db).await?;
+
+	let salt = Salt::generate();
+	let key = KEYSTORE_BACKUP_HASHING_ALGORITHM.hash(
+		Protected::new(passphrase.as_bytes().to_vec()),
+		salt,
+		None,
+	)?;
+
+	let (nonce, ciphertext) = seal_storedkeys(key, &keys, KEYSTORE_BACKUP_MAGIC).await?;
+
+	Ok([
+		KEYSTORE_BACKUP_MAGIC.as_slice(),
+		&salt.0,
+		nonce.as_ref(),
+		&ciphertext,
+	]
+	.concat())
+}
+
+/// This decrypts a blob created by [`export_keystore`] and writes the keys it contains to prisma
+///
+/// Keys that already exist are updated. Returns the number of keys that were written.
+pub async fn import_keystore(
+	db: &PrismaClient,
+	passphrase: &str,
+	backup: &[u8],
+) -> Result<usize, LibraryManagerError> {
+	let header_len = KEYSTORE_BACKUP_MAGIC.len() + SALT_LEN + KEYSTORE_BACKUP_ALGORITHM.nonce_len();
+	if backup.len() < header_len || !backup.starts_with(KEYSTORE_BACKUP_MAGIC) {
+		return Err(LibraryManagerError::InvalidKeystoreBackup);
+	}
+
+	let (salt, rest) = backup[KEYSTORE_BACKUP_MAGIC.len()..].split_at(SALT_LEN);
+	let (nonce, ciphertext) = rest.split_at(KEYSTORE_BACKUP_ALGORITHM.nonce_len());
+
+	let salt = Salt::try_from(salt.to_vec())?;
+	let key = KEYSTORE_BACKUP_HASHING_ALGORITHM.hash(
+		Protected::new(passphrase.as_bytes().to_vec()),
+		salt,
+		None,
+	)?;
+
+	let keys = open_storedkeys(
+		key,
+		Nonce::try_from(nonce.to_vec())?,
+		ciphertext,
+		KEYSTORE_BACKUP_MAGIC,
+	)
+	.await?;
+
+	write_storedkeys_to_db(db, &keys).await
+}
+
+/// This exports every `StoredKey` in prisma to a keyring file at `output`, encrypted with `wrapping_key`
+///
+/// `wrapping_key` must be 32 bytes long, and is used as is to encrypt the keys with AES-256-GCM.
+/// Memory-only keys are never in prisma, so they're never exported. Returns the number of keys that were exported.
+pub async fn export_keyring(
+	db: &PrismaClient,
+	output: &Path,
+	wrapping_key: &Protected<Vec<u8>>,
+) -> Result<usize, LibraryManagerError> {
+	let keys = read_storedkeys_for_export(db).await?;
+	let header = [KEYRING_MAGIC.as_slice(), &[KEYRING_VERSION]].concat();
+
+	let (nonce, ciphertext) = seal_storedkeys(keyring_key(wrapping_key)?, &keys, &header).await?;
+
+	fs::write(output, [&header, nonce.as_ref(), &ciphertext].concat())
+		.await
+		.map_err(|e| FileIOError::from((output, e)))?;
+
+	Ok(keys.len())
+}
+
+/// ConflictPolicy decides what [`import_keyring`] does with keys that are already in prisma,
+/// and what [`merge_libraries`](crate::library::merge_libraries) does with rows that are already in the library being merged into.
+///
+/// Soft-deleted keys count as already being there, so only `Overwrite` brings them back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+	/// Keep the existing key
+	Skip,
+	/// Replace the existing key with the imported one
+	Overwrite,
+	/// Abort the whole import with `LibraryManagerError::KeyAlreadyExists` (or `LibraryManagerError::MergeConflict`, for rows other than keys)
+	Fail,
+}
+
+/// ImportSummary describes what [`import_keyring`] did with each of the keys in the keyring.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+	/// Keys that weren't in prisma yet
+	pub imported: usize,
+	/// Keys that were already in prisma and left alone
+	pub skipped: usize,
+	/// Keys that were already in prisma and replaced
+	pub overwritten: usize,
+}
+
+/// This decrypts a keyring file created by [`export_keyring`] and writes the keys it contains to prisma
+///
+/// Keys that already exist are handled according to `on_conflict`. The keys are all written in one transaction,
+/// so a failed import leaves prisma untouched.
+pub async fn import_keyring(
+	db: &PrismaClient,
+	input: &Path,
+	wrapping_key: &Protected<Vec<u8>>,
+	on_conflict: ConflictPolicy,
+) -> Result<ImportSummary, LibraryManagerError> {
+	let keyring = fs::read(input)
+		.await
+		.map_err(|e| FileIOError::from((input, e)))?;
+
+	let header_len = KEYRING_MAGIC.len() + 1;
+	if keyring.len() < header_len + KEYSTORE_BACKUP_ALGORITHM.nonce_len()
+		|| !keyring.starts_with(KEYRING_MAGIC)
+		|| keyring[KEYRING_MAGIC.len()] != KEYRING_VERSION
+	{
+		return Err(LibraryManagerError::InvalidKeystoreBackup);
+	}
+
+	let (header, rest) = keyring.split_at(header_len);
+	let (nonce, ciphertext) = rest.split_at(KEYSTORE_BACKUP_ALGORITHM.nonce_len());
+
+	let keys = open_storedkeys(
+		keyring_key(wrapping_key)?,
+		Nonce::try_from(nonce.to_vec())?,
+		ciphertext,
+		header,
+	)
+	.await?;
+
+	db._transaction()
+		.run(|tx| async move {
+			let mut summary = ImportSummary::default();
+
+			for key in keys.iter().filter(|k| !k.memory_only) {
+				match (key_row_exists(&tx, key.uuid).await?, on_conflict) {
+					(false, _) => summary.imported += 1,
+					(true, ConflictPolicy::Skip) => {
+						summary.skipped += 1;
+						continue;
+					}
+					(true, ConflictPolicy::Overwrite) => summary.overwritten += 1,
+					(true, ConflictPolicy::Fail) => {
+						return Err(LibraryManagerError::KeyAlreadyExists(key.uuid))
+					}
+				}
+
+				write_storedkey_to_db(&tx, key).await?;
+			}
+
+			Ok(summary)
+		})
+		.await
+}
+
+fn keyring_key(wrapping_key: &Protected<Vec<u8>>) -> Result<Key, LibraryManagerError> {
+	Ok(Key::new(to_array(wrapping_key.expose())?))
+}
+
+/// Reads every `StoredKey` that's to be backed up
+///
+/// A backup that's missing keys is worse than no backup, so unlike [`read_all_storedkeys_from_db`] unreadable rows fail the read.
+async fn read_storedkeys_for_export(
+	db: &PrismaClient,
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	db.key()
+		.find_many(vec![active_key()])
+		.exec()
+		.await?
+		.into_iter()
+		.map(storedkey_from_row)
+		.collect()
+}
+
+/// Serializes `keys` and encrypts them with `key`, authenticating `aad` alongside them
+async fn seal_storedkeys(
+	key: Key,
+	keys: &[StoredKey],
+	aad: &[u8],
+) -> Result<(Nonce, Vec<u8>), LibraryManagerError> {
+	let nonce = Nonce::generate(KEYSTORE_BACKUP_ALGORITHM)?;
+	let plaintext = key_json("keystore", serde_json::to_vec(keys))?;
+
+	let ciphertext =
+		Encryptor::encrypt_bytes(key, nonce, KEYSTORE_BACKUP_ALGORITHM, &plaintext, aad).await?;
+
+	Ok((nonce, ciphertext))
+}
+
+/// The inverse of [`seal_storedkeys`]. A wrong key (or tampered `aad`) is reported as an incorrect password
+async fn open_storedkeys(
+	key: Key,
+	nonce: Nonce,
+	ciphertext: &[u8],
+	aad: &[u8],
+) -> Result<Vec<StoredKey>, LibraryManagerError> {
+	let plaintext =
+		Decryptor::decrypt_bytes(key, nonce, KEYSTORE_BACKUP_ALGORITHM, ciphertext, aad)
+			.await
+			.map_err(|_| sd_crypto::Error::IncorrectPassword)?;
+
+	key_json("keystore", serde_json::from_slice(plaintext.expose()))
+}
+
+/// KeyValidationError is returned when a `StoredKey` is inconsistent, and would fail to decrypt if it was persisted.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum KeyValidationError {
+	#[error(
+		"'{field}' is {actual} bytes long, but the key's algorithm uses {expected} byte nonces"
+	)]
+	InvalidNonceLength {
+		field: &'static str,
+		expected: usize,
+		actual: usize,
+	},
+	#[error("the encrypted key is {actual} bytes long, expected {expected}")]
+	InvalidKeyLength { expected: usize, actual: usize },
+}
+
+/// This checks that the nonces of a `StoredKey` match its algorithm, and that its encrypted key has the expected length
+///
+/// The salts and the master key are fixed-size arrays, so they can't have the wrong length.
+pub fn validate_storedkey(key: &StoredKey) -> Result<(), KeyValidationError> {
+	let expected = key.algorithm.nonce_len();
+
+	for (field, nonce) in [
+		("master_key_nonce", &key.master_key_nonce),
+		("key_nonce", &key.key_nonce),
+	] {
+		if nonce.len() != expected {
+			return Err(KeyValidationError::InvalidNonceLength {
+				field,
+				expected,
+				actual: nonce.len(),
+			});
+		}
+	}
+
+	if key.key.len() != ENCRYPTED_KEY_LEN {
+		return Err(KeyValidationError::InvalidKeyLength {
+			expected: ENCRYPTED_KEY_LEN,
+			actual: key.key.len(),
+		});
+	}
+
+	Ok(())
+}
+
+/// This reconstructs a `StoredKey` from a raw prisma `key` row
+///
+/// Keys that come from the database are never memory-only, and if the row has a checksum it's verified against the key material.
+/// Rows written with an older `version` are upgraded by [`migrate_stored_key`] as they're read.
+pub(crate) fn storedkey_from_row(row: key::Data) -> Result<StoredKey, LibraryManagerError> {
+	let raw_version = key_json("version", serde_json::from_str(&row.version))?;
+
+	migrate_stored_key(raw_version, &row)
+}
+
+/// This maps the `version` of a key row onto the current `StoredKey` layout, and then upgrades the key to the latest `StoredKeyVersion`
+///
+/// Besides the serialized `StoredKeyVersion`, this accepts the legacy `"V001"` string and bare version numbers.
+/// Keys that are already on the latest version pass through unchanged.
+fn migrate_stored_key(
+	raw_version: serde_json::Value,
+	row: &key::Data,
+) -> Result<StoredKey, LibraryManagerError> {
+	let version = match raw_version {
+		serde_json::Value::String(version) if version == "V001" => StoredKeyVersion::V1,
+		serde_json::Value::Number(version) if version.as_u64() == Some(1) => StoredKeyVersion::V1,
+		version => key_json("version", serde_json::from_value(version))?,
+	};
+
+	let key = StoredKey {
+		uuid: parse_uuid(&row.uuid)?,
+		version,
+		key_type: key_json("key_type", serde_json::from_str(&row.key_type))?,
+		algorithm: key_json("algorithm", serde_json::from_str(&row.algorithm))?,
+		hashing_algorithm: key_json(
+			"hashing_algorithm",
+			serde_json::from_str(&row.hashing_algorithm),
+		)?,
+		content_salt: key_column("content_salt", row.content_salt.clone())?,
+		master_key: key_column("master_key", row.master_key.clone())?,
+		master_key_nonce: key_column("master_key_nonce", row.master_key_nonce.clone())?,
+		key_nonce: key_column("key_nonce", row.key_nonce.clone())?,
+		key: row.key.clone(),
+		salt: key_column("salt", row.salt.clone())?,
+		memory_only: false,
+		automount: row.automount,
+		expires_at: row.expires_at.map(Into::into),
+	};
+
+	// the checksum covers the key material as it was stored, so it has to be verified before the key is upgraded
+	match &row.checksum {
+		Some(checksum) if *checksum != storedkey_checksum(&key) => {
+			Err(LibraryManagerError::KeyChecksumMismatch { uuid: key.uuid })
+		}
+		_ => Ok(migrate_storedkey(key)?),
+	}
+}
+
+/// Attaches the name of the column to a failed (de)serialization of one of the `StoredKey` JSON columns
+fn key_json<T>(
+	field: &'static str,
+	res: Result<T, serde_json::Error>,
+) -> Result<T, LibraryManagerError> {
+	res.map_err(|source| LibraryManagerError::KeySerialization { field, source })
+}
+
+/// Converts a byte column into one of the fixed-size crypto types, reporting which column had the wrong length
+fn key_column<T: TryFrom<Vec<u8>>>(
+	column: &'static str,
+	bytes: Vec<u8>,
+) -> Result<T, LibraryManagerError> {
+	let len = bytes.len();
+	T::try_from(bytes).map_err(|_| LibraryManagerError::InvalidKeyColumnLength { column, len })
+}
+
+/// A user key with made up key material, for the tests that need some rows in the `key` table
+#[cfg(test)]
+pub(crate) fn test_key(algorithm: Algorithm) -> StoredKey {
+	use sd_crypto::{keys::keymanager::StoredKeyType, types::EncryptedKey};
+
+	StoredKey {
+		uuid: Uuid::new_v4(),
+		version: StoredKeyVersion::V1,
+		key_type: StoredKeyType::User,
+		algorithm,
+		hashing_algorithm: HashingAlgorithm::Argon2id(Params::Standard),
+		content_salt: Salt::generate(),
+		master_key: EncryptedKey([7; ENCRYPTED_KEY_LEN]),
+		master_key_nonce: Nonce::generate(algorithm).unwrap(),
+		key_nonce: Nonce::generate(algorithm).unwrap(),
+		key: vec![1; 48],
+		salt: Salt::generate(),
+		memory_only: false,
+		automount: false,
+		expires_at: None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::{
+		audit::{set_audit_sink, AuditSink},
+		db::test_db,
+	};
+
+	use std::sync::{Arc, Mutex};
+
+	use prisma_client_rust::raw;
+	use sd_crypto::types::EncryptedKey;
+
+	#[tokio::test]
+	async fn memory_only_keys_are_audited() {
+		struct CaptureSink(Arc<Mutex<Vec<AuditEvent>>>);
+
+		impl AuditSink for CaptureSink {
+			fn record(&self, event: &AuditEvent) {
+				self.0.lock().unwrap().push(event.clone());
+			}
+		}
+
+		let events = Arc::new(Mutex::new(Vec::new()));
+		set_audit_sink(CaptureSink(events.clone()));
+
+		let (_dir, client) = test_db().await;
+		let key = StoredKey {
+			memory_only: true,
+			..test_key(Algorithm::XChaCha20Poly1305)
+		};
+		write_storedkey_to_db(&client, &key).await.unwrap();
+
+		assert!(events.lock().unwrap().contains(&AuditEvent::KeySkipped {
+			uuid: key.uuid,
+			reason: SkipReason::MemoryOnly,
+		}));
+		assert_eq!(client.key().count(vec![]).exec().await.unwrap(), 0);
+	}
+
+	#[tokio::test]
+	async fn keystore_backup_round_trip() {
+		let (_dir, source) = test_db().await;
+		let keys = vec![
+			test_key(Algorithm::XChaCha20Poly1305),
+			test_key(Algorithm::Aes256Gcm),
+		];
+		write_storedkeys_to_db(&source, &keys).await.unwrap();
+
+		let backup = export_keystore(&source, "correct horse battery staple")
+			.await
+			.unwrap();
+		assert!(backup.starts_with(KEYSTORE_BACKUP_MAGIC));
+
+		let (_dir, target) = test_db().await;
+		assert!(matches!(
+			import_keystore(&target, "wrong passphrase", &backup).await,
+			Err(LibraryManagerError::KeyManager(
+				sd_crypto::Error::IncorrectPassword
+			))
+		));
+		assert_eq!(
+			import_keystore(&target, "correct horse battery staple", &backup)
+				.await
+				.unwrap(),
+			2
+		);
+
+		for key in &keys {
+			assert!(read_storedkey_from_db(&target, key.uuid).await.unwrap() == *key);
+		}
+	}
+
+	/// A backup in the current format, encrypted with the passphrase "correct horse battery staple",
+	/// a salt of `[1; SALT_LEN]` and a nonce of `[2; 8]`. If this stops decrypting, existing backups can't be restored anymore
+	const KEYSTORE_BACKUP_TEST_VECTOR: &[u8] = include_bytes!("fixtures/keystore_backup_v1.bin");
+
+	#[tokio::test]
+	async fn keystore_backup_test_vector() {
+		use sd_crypto::keys::keymanager::StoredKeyType;
+
+		let expected = [
+			StoredKey {
+				uuid: Uuid::parse_str("4e0a8c3f-6b1d-4c2e-9f7a-1d2c3b4a5f60").unwrap(),
+				version: StoredKeyVersion::V1,
+				key_type: StoredKeyType::User,
+				algorithm: Algorithm::XChaCha20Poly1305,
+				hashing_algorithm: HashingAlgorithm::Argon2id(Params::Standard),
+				content_salt: Salt([3; SALT_LEN]),
+				master_key: EncryptedKey([4; ENCRYPTED_KEY_LEN]),
+				master_key_nonce: Nonce::XChaCha20Poly1305([5; 20]),
+				key_nonce: Nonce::XChaCha20Poly1305([6; 20]),
+				key: vec![7; ENCRYPTED_KEY_LEN],
+				salt: Salt([8; SALT_LEN]),
+				memory_only: false,
+				automount: false,
+				expires_at: None,
+			},
+			StoredKey {
+				uuid: Uuid::parse_str("9b7e2d41-0c5a-4f83-b6e9-7a8d9c0b1e2f").unwrap(),
+				version: StoredKeyVersion::V1,
+				key_type: StoredKeyType::User,
+				algorithm: Algorithm::Aes256Gcm,
+				hashing_algorithm: HashingAlgorithm::BalloonBlake3(Params::Hardened),
+				content_salt: Salt([9; SALT_LEN]),
+				master_key: EncryptedKey([10; ENCRYPTED_KEY_LEN]),
+				master_key_nonce: Nonce::Aes256Gcm([11; 8]),
+				key_nonce: Nonce::Aes256Gcm([12; 8]),
+				key: vec![13; ENCRYPTED_KEY_LEN],
+				salt: Salt([14; SALT_LEN]),
+				memory_only: false,
+				automount: true,
+				expires_at: Some("2030-01-01T00:00:00Z".parse().unwrap()),
+			},
+		];
+
+		assert_eq!(
+			&KEYSTORE_BACKUP_TEST_VECTOR[KEYSTORE_BACKUP_MAGIC.len()..][..SALT_LEN + 8],
+			[[1; SALT_LEN].as_slice(), &[2; 8]].concat()
+		);
+
+		let (_dir, client) = test_db().await;
+		assert_eq!(
+			import_keystore(
+				&client,
+				"correct horse battery staple",
+				KEYSTORE_BACKUP_TEST_VECTOR
+			)
+			.await
+			.unwrap(),
+			expected.len()
+		);
+
+		for key in &expected {
+			assert!(read_storedkey_from_db(&client, key.uuid).await.unwrap() == *key);
+		}
+	}
+
+	#[tokio::test]
+	async fn keyring_file_round_trip() {
+		let (dir, source) = test_db().await;
+		let keys = vec![
+			test_key(Algorithm::XChaCha20Poly1305),
+			test_key(Algorithm::Aes256Gcm),
+		];
+		write_storedkeys_to_db(&source, &keys).await.unwrap();
+
+		let path = dir.path().join("keys.sdkeyring");
+		let wrapping_key = Protected::new(vec![9; 32]);
+		assert_eq!(
+			export_keyring(&source, &path, &wrapping_key).await.unwrap(),
+			2
+		);
+
+		let (_dir, target) = test_db().await;
+		assert!(matches!(
+			import_keyring(
+				&target,
+				&path,
+				&Protected::new(vec![8; 32]),
+				ConflictPolicy::Fail
+			)
+			.await,
+			Err(LibraryManagerError::KeyManager(
+				sd_crypto::Error::IncorrectPassword
+			))
+		));
+		assert!(matches!(
+			import_keyring(
+				&target,
+				&path,
+				&Protected::new(vec![9; 16]),
+				ConflictPolicy::Fail
+			)
+			.await,
+			Err(LibraryManagerError::KeyManager(
+				sd_crypto::Error::VecArrSizeMismatch
+			))
+		));
+
+		// one of the keys is already on the target, so a failing import must not write the other
+		write_storedkey_to_db(&target, &keys[1]).await.unwrap();
+		assert!(matches!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Fail).await,
+			Err(LibraryManagerError::KeyAlreadyExists(uuid)) if uuid == keys[1].uuid
+		));
+		assert_eq!(target.key().count(vec![]).exec().await.unwrap(), 1);
+
+		assert_eq!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Skip)
+				.await
+				.unwrap(),
+			ImportSummary {
+				imported: 1,
+				skipped: 1,
+				overwritten: 0
+			}
+		);
+		assert_eq!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Overwrite)
+				.await
+				.unwrap(),
+			ImportSummary {
+				imported: 0,
+				skipped: 0,
+				overwritten: 2
+			}
+		);
+
+		for key in &keys {
+			assert!(read_storedkey_from_db(&target, key.uuid).await.unwrap() == *key);
+		}
+	}
+
+	#[tokio::test]
+	async fn importing_a_keyring_only_restores_deleted_keys_when_overwriting() {
+		let (dir, source) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&source, &key).await.unwrap();
+
+		let path = dir.path().join("keys.sdkeyring");
+		let wrapping_key = Protected::new(vec![9; 32]);
+		export_keyring(&source, &path, &wrapping_key).await.unwrap();
+
+		let (_dir, target) = test_db().await;
+		write_storedkey_to_db(&target, &key).await.unwrap();
+		soft_delete_storedkey(&target, key.uuid).await.unwrap();
+
+		assert!(matches!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Fail).await,
+			Err(LibraryManagerError::KeyAlreadyExists(uuid)) if uuid == key.uuid
+		));
+		assert_eq!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Skip)
+				.await
+				.unwrap(),
+			ImportSummary {
+				imported: 0,
+				skipped: 1,
+				overwritten: 0
+			}
+		);
+		assert!(!key_exists(&target, key.uuid).await.unwrap());
+
+		assert_eq!(
+			import_keyring(&target, &path, &wrapping_key, ConflictPolicy::Overwrite)
+				.await
+				.unwrap()
+				.overwritten,
+			1
+		);
+		assert!(key_exists(&target, key.uuid).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn import_keystore_rejects_malformed_backups() {
+		let (_dir, client) = test_db().await;
+
+		for backup in [
+			b"".as_slice(),
+			b"sdkeybk1".as_slice(),
+			b"notabackup00000000000000000000000000".as_slice(),
+		] {
+			assert!(matches!(
+				import_keystore(&client, "passphrase", backup).await,
+				Err(LibraryManagerError::InvalidKeystoreBackup)
+			));
+		}
+	}
+
+	#[tokio::test]
+	async fn stream_storedkeys_spans_pages() {
+		use futures::TryStreamExt;
+
+		let (_dir, client) = test_db().await;
+		let keys = (0..STOREDKEY_PAGE_SIZE * 2 + 1)
+			.map(|_| test_key(Algorithm::XChaCha20Poly1305))
+			.collect::<Vec<_>>();
+		write_storedkeys_to_db(&client, &keys).await.unwrap();
+
+		let streamed = stream_storedkeys(&client)
+			.try_collect::<Vec<_>>()
+			.await
+			.unwrap();
+
+		assert!(streamed == keys);
+	}
+
+	#[tokio::test]
+	async fn list_storedkeys_paginated_returns_requested_page() {
+		let (_dir, client) = test_db().await;
+		let keys = (0..120)
+			.map(|_| test_key(Algorithm::XChaCha20Poly1305))
+			.collect::<Vec<_>>();
+		write_storedkeys_to_db(&client, &keys).await.unwrap();
+
+		let first = list_storedkeys_paginated(&client, 0, 50).await.unwrap();
+		assert!(first == keys[..50]);
+
+		let middle = list_storedkeys_paginated(&client, 50, 50).await.unwrap();
+		assert!(middle == keys[50..100]);
+
+		let last = list_storedkeys_paginated(&client, 100, 50).await.unwrap();
+		assert!(last == keys[100..]);
+
+		assert!(list_storedkeys_paginated(&client, 500, 50)
+			.await
+			.unwrap()
+			.is_empty());
+	}
+
+	#[tokio::test]
+	async fn list_storedkeys_paginated_rejects_invalid_bounds() {
+		let (_dir, client) = test_db().await;
+
+		for (offset, limit) in [(0, 0), (0, MAX_STOREDKEY_PAGE_LIMIT + 1), (-1, 50)] {
+			assert!(matches!(
+				list_storedkeys_paginated(&client, offset, limit).await,
+				Err(LibraryManagerError::InvalidPagination { .. })
+			));
+		}
+	}
+
+	#[tokio::test]
+	async fn stream_storedkeys_yields_every_row() {
+		use futures::TryStreamExt;
+
+		let (_dir, client) = test_db().await;
+		let keys = (0..STOREDKEY_PAGE_SIZE + 50)
+			.map(|_| test_key(Algorithm::XChaCha20Poly1305))
+			.collect::<Vec<_>>();
+		write_storedkeys_to_db(&client, &keys).await.unwrap();
+
+		let rows = client.key().count(vec![]).exec().await.unwrap();
+		let streamed = stream_storedkeys(&client)
+			.try_fold(0, |count, _| async move { Ok(count + 1) })
+			.await
+			.unwrap();
+
+		assert_eq!(streamed, rows);
+	}
+
+	#[tokio::test]
+	async fn deduplicate_keys_keeps_newest() {
+		let (_dir, client) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&client, &key).await.unwrap();
+
+		// duplicates can only exist without the unique index on `uuid`
+		client
+			._execute_raw(raw!("DROP INDEX \"key_uuid_key\""))
+			.exec()
+			.await
+			.unwrap();
+		client
+			._execute_raw(raw!(
+				"INSERT INTO \"key\" (uuid, version, key_type, name, \"default\", date_created, \
+					algorithm, hashing_algorithm, content_salt, master_key, master_key_nonce, \
+					key_nonce, \"key\", salt, checksum, automount) \
+				SELECT uuid, version, key_type, name, \"default\", date_created, algorithm, \
+					hashing_algorithm, content_salt, master_key, master_key_nonce, key_nonce, \
+					\"key\", salt, checksum, automount FROM \"key\""
+			))
+			.exec()
+			.await
+			.unwrap();
+
+		let groups = detect_duplicate_keys(&client).await.unwrap();
+		assert_eq!(groups.len(), 1);
+		assert_eq!(groups[0].uuid, key.uuid);
+		assert_eq!(groups[0].ids.len(), 2);
+
+		assert!(matches!(
+			deduplicate_keys(&client, DeduplicationStrategy::Error).await,
+			Err(LibraryManagerError::DuplicateKeys(uuids)) if uuids == vec![key.uuid]
+		));
+		assert_eq!(
+			deduplicate_keys(&client, DeduplicationStrategy::KeepNewest)
+				.await
+				.unwrap(),
+			1
+		);
+
+		let remaining = client.key().find_many(vec![]).exec().await.unwrap();
+		assert_eq!(remaining.len(), 1);
+		assert_eq!(remaining[0].id, groups[0].ids[1]);
+	}
+
+	#[tokio::test]
+	async fn reencrypt_master_keys_rolls_back_on_failure() {
+		let (_dir, client) = test_db().await;
+		let stored = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&client, &stored).await.unwrap();
+
+		let mut rotated = stored.clone();
+		rotated.master_key = EncryptedKey([2; ENCRYPTED_KEY_LEN]);
+		rotated.master_key_nonce = Nonce::generate(Algorithm::XChaCha20Poly1305).unwrap();
+
+		// the second key was never written, so the whole batch has to be rolled back
+		let missing = test_key(Algorithm::XChaCha20Poly1305);
+		let mut keys = [rotated.clone(), missing.clone()];
+		assert!(matches!(
+			reencrypt_master_keys(&client, &mut keys).await,
+			Err(LibraryManagerError::KeyNotFound(uuid)) if uuid == missing.uuid
+		));
+		assert!(read_storedkey_from_db(&client, stored.uuid).await.unwrap() == stored);
+
+		reencrypt_master_keys(&client, &mut [rotated.clone()])
+			.await
+			.unwrap();
+		assert!(read_storedkey_from_db(&client, stored.uuid).await.unwrap() == rotated);
+	}
+
+	#[tokio::test]
+	async fn write_storedkey_rejects_mismatched_nonces() {
+		let (_dir, client) = test_db().await;
+		let key = StoredKey {
+			key_nonce: Nonce::generate(Algorithm::Aes256Gcm).unwrap(),
+			..test_key(Algorithm::XChaCha20Poly1305)
+		};
+
+		assert!(matches!(
+			write_storedkey_to_db(&client, &key).await,
+			Err(LibraryManagerError::KeyValidation(
+				KeyValidationError::InvalidNonceLength {
+					field: "key_nonce",
+					expected: 20,
+					actual: 8,
+				}
+			))
+		));
+		assert_eq!(client.key().count(vec![]).exec().await.unwrap(), 0);
+
+		assert_eq!(
+			validate_storedkey(&StoredKey {
+				key: vec![0; 32],
+				..test_key(Algorithm::Aes256Gcm)
+			}),
+			Err(KeyValidationError::InvalidKeyLength {
+				expected: ENCRYPTED_KEY_LEN,
+				actual: 32,
+			})
+		);
+	}
+
+	#[tokio::test]
+	async fn write_storedkey_returns_row_id() {
+		let (_dir, client) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+
+		let id = write_storedkey_to_db(&client, &key).await.unwrap().unwrap();
+		let row = client
+			.key()
+			.find_unique(key::uuid::equals(key.uuid.to_string()))
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(row.id, id);
+
+		// updating the key keeps its row
+		assert_eq!(
+			write_storedkey_to_db(&client, &key).await.unwrap(),
+			Some(id)
+		);
+		assert_eq!(
+			write_storedkey_to_db(
+				&client,
+				&StoredKey {
+					memory_only: true,
+					..test_key(Algorithm::XChaCha20Poly1305)
+				}
+			)
+			.await
+			.unwrap(),
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn purge_rotated_keys_keeps_the_latest_versions() {
+		let (_dir, client) = test_db().await;
+		let family = Uuid::new_v4();
+		let versions = (0..3)
+			.map(|_| test_key(Algorithm::XChaCha20Poly1305))
+			.collect::<Vec<_>>();
+		let unrotated = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkeys_to_db(&client, &versions).await.unwrap();
+		write_storedkey_to_db(&client, &unrotated).await.unwrap();
+		for version in &versions {
+			set_key_family(&client, version.uuid, family).await.unwrap();
+		}
+
+		assert_eq!(purge_rotated_keys(&client, 1).await.unwrap(), 2);
+		assert_eq!(purge_rotated_keys(&client, 1).await.unwrap(), 0);
+
+		let remaining = read_all_storedkeys_from_db(&client, true)
+			.await
+			.unwrap()
+			.keys
+			.into_iter()
+			.map(|key| key.uuid)
+			.collect::<Vec<_>>();
+		assert_eq!(remaining, vec![versions[2].uuid, unrotated.uuid]);
+	}
+
+	#[tokio::test]
+	async fn rename_key_only_changes_the_name() {
+		let (_dir, db) = test_db().await;
+
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&db, &key).await.unwrap();
+
+		rename_key(&db, key.uuid, "Photos".to_string())
+			.await
+			.unwrap();
+
+		let row = db
+			.key()
+			.find_unique(key::uuid::equals(key.uuid.to_string()))
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(row.name.as_deref(), Some("Photos"));
+		assert!(read_storedkey_from_db(&db, key.uuid).await.unwrap() == key);
+
+		let unknown = Uuid::new_v4();
+		assert!(matches!(
+			rename_key(&db, unknown, "Photos".to_string()).await,
+			Err(LibraryManagerError::KeyNotFound(uuid)) if uuid == unknown
+		));
+	}
+
+	#[tokio::test]
+	async fn content_salts_must_be_unique_within_a_batch() {
+		let (_dir, db) = test_db().await;
+		let first = test_key(Algorithm::XChaCha20Poly1305);
+		let reused = StoredKey {
+			content_salt: first.content_salt,
+			..test_key(Algorithm::XChaCha20Poly1305)
+		};
+
+		assert!(matches!(
+			write_storedkeys_to_db(&db, &[first.clone(), reused.clone()]).await,
+			Err(LibraryManagerError::DuplicateContentSalt { uuid }) if uuid == reused.uuid
+		));
+		assert!(read_all_storedkeys_from_db(&db, true)
+			.await
+			.unwrap()
+			.keys
+			.is_empty());
+
+		// writing the same key twice isn't a reuse
+		assert_eq!(
+			write_storedkeys_to_db(&db, &[first.clone(), first])
+				.await
+				.unwrap(),
+			2
+		);
+	}
+
+	#[tokio::test]
+	async fn content_salts_must_not_be_used_by_existing_keys() {
+		let (_dir, db) = test_db().await;
+		let existing = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkeys_to_db(&db, &[existing.clone()])
+			.await
+			.unwrap();
+		soft_delete_storedkey(&db, existing.uuid).await.unwrap();
+
+		let fresh = test_key(Algorithm::XChaCha20Poly1305);
+		let reused = StoredKey {
+			content_salt: existing.content_salt,
+			..test_key(Algorithm::Aes256Gcm)
+		};
+
+		assert!(matches!(
+			write_storedkeys_to_db(&db, &[fresh.clone(), reused.clone()]).await,
+			Err(LibraryManagerError::DuplicateContentSalt { uuid }) if uuid == reused.uuid
+		));
+		assert_eq!(
+			read_all_storedkeys_from_db(&db, true)
+				.await
+				.unwrap()
+				.keys
+				.len(),
+			1
+		);
+
+		// rewriting the key that already has the salt is fine
+		assert_eq!(
+			write_storedkeys_to_db(&db, &[existing, fresh])
+				.await
+				.unwrap(),
+			2
+		);
+	}
+
+	#[tokio::test]
+	async fn soft_deleted_keys_are_hidden_until_requested() {
+		let (_dir, client) = test_db().await;
+		let kept = test_key(Algorithm::XChaCha20Poly1305);
+		let deleted = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkeys_to_db(&client, &[kept.clone(), deleted.clone()])
+			.await
+			.unwrap();
+
+		soft_delete_storedkey(&client, deleted.uuid).await.unwrap();
+		assert!(matches!(
+			soft_delete_storedkey(&client, deleted.uuid).await,
+			Err(LibraryManagerError::KeyNotFound(_))
+		));
+
+		let active = read_all_storedkeys_from_db(&client, false).await.unwrap();
+		assert!(active.keys == vec![kept.clone()]);
+		assert!(matches!(
+			read_storedkey_from_db(&client, deleted.uuid).await,
+			Err(LibraryManagerError::KeyNotFound(_))
+		));
+
+		let all = read_all_storedkeys_from_db(&client, true).await.unwrap();
+		assert_eq!(all.keys.len(), 2);
+
+		// tombstones are only purged once they're old enough
+		assert_eq!(
+			purge_deleted_keys(&client, Duration::from_secs(3600))
+				.await
+				.unwrap(),
+			0
+		);
+		assert_eq!(
+			purge_deleted_keys(&client, Duration::ZERO).await.unwrap(),
+			1
+		);
+		assert_eq!(
+			read_all_storedkeys_from_db(&client, true)
+				.await
+				.unwrap()
+				.keys
+				.len(),
+			1
+		);
+	}
+
+	#[tokio::test]
+	async fn list_storedkeys_by_algorithm_filters() {
+		let (_dir, db) = test_db().await;
+
+		let xchacha = test_key(Algorithm::XChaCha20Poly1305);
+		let aes = [
+			test_key(Algorithm::Aes256Gcm),
+			test_key(Algorithm::Aes256Gcm),
+		];
+
+		write_storedkey_to_db(&db, &xchacha).await.unwrap();
+		write_storedkeys_to_db(&db, &aes).await.unwrap();
+
+		let keys = list_storedkeys_by_algorithm(&db, &Algorithm::Aes256Gcm)
+			.await
+			.unwrap();
+		assert_eq!(keys.len(), 2);
+		assert!(keys.iter().all(|k| aes.contains(k)));
+
+		let keys = list_storedkeys_by_algorithm(&db, &Algorithm::XChaCha20Poly1305)
+			.await
+			.unwrap();
+		assert!(keys == [xchacha]);
+	}
+
+	#[tokio::test]
+	async fn expired_and_expiring_keys_are_listed() {
+		let (_dir, db) = test_db().await;
+
+		let now = Utc::now();
+		let [expired, expiring, later, never] = [
+			Some(now - chrono::Duration::days(1)),
+			Some(now + chrono::Duration::days(3)),
+			Some(now + chrono::Duration::days(30)),
+			None,
+		]
+		.map(|expires_at| StoredKey {
+			expires_at,
+			..test_key(Algorithm::XChaCha20Poly1305)
+		});
+
+		write_storedkeys_to_db(&db, &[expired.clone(), expiring.clone(), later, never])
+			.await
+			.unwrap();
+
+		let uuids = |keys: Vec<StoredKey>| keys.into_iter().map(|k| k.uuid).collect::<Vec<_>>();
+
+		assert_eq!(uuids(list_expired_keys(&db).await.unwrap()), [expired.uuid]);
+		assert_eq!(
+			uuids(
+				list_expiring_keys(&db, Duration::from_secs(7 * 24 * 60 * 60))
+					.await
+					.unwrap()
+			),
+			[expired.uuid, expiring.uuid]
+		);
+	}
+	#[tokio::test]
+	async fn old_storedkey_versions_need_migration() {
+		let (_dir, db) = test_db().await;
+		let current = test_key(Algorithm::XChaCha20Poly1305);
+		let old = test_key(Algorithm::Aes256Gcm);
+		write_storedkeys_to_db(&db, &[current.clone(), old.clone()])
+			.await
+			.unwrap();
+
+		assert!(list_storedkeys_needing_migration(&db)
+			.await
+			.unwrap()
+			.is_empty());
+
+		// there's only ever been one version, so a synthetic older one stands in for it
+		db.key()
+			.update(
+				key::uuid::equals(old.uuid.to_string()),
+				vec![key::version::set("\"V0\"".to_string())],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		assert_eq!(
+			list_storedkeys_needing_migration(&db).await.unwrap(),
+			vec![old.uuid]
+		);
+		assert!(migrate_storedkey(current.clone()).unwrap() == current);
+	}
+
+	#[tokio::test]
+	async fn legacy_storedkey_versions_are_upgraded_on_read() {
+		let (_dir, db) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&db, &key).await.unwrap();
+
+		for legacy in ["\"V001\"", "1"] {
+			db.key()
+				.update(
+					key::uuid::equals(key.uuid.to_string()),
+					vec![key::version::set(legacy.to_string())],
+				)
+				.exec()
+				.await
+				.unwrap();
+
+			assert!(read_storedkey_from_db(&db, key.uuid).await.unwrap() == key);
+		}
+
+		db.key()
+			.update(
+				key::uuid::equals(key.uuid.to_string()),
+				vec![key::version::set("\"V9\"".to_string())],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		assert!(matches!(
+			read_storedkey_from_db(&db, key.uuid).await,
+			Err(LibraryManagerError::KeySerialization {
+				field: "version",
+				..
+			})
+		));
+	}
+
+	#[tokio::test]
+	async fn duplicate_key_uuid_is_reported_as_key_already_exists() {
+		let (_dir, db) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		write_storedkey_to_db(&db, &key).await.unwrap();
+
+		let err = db
+			.key()
+			.create(
+				key.uuid.to_string(),
+				String::new(),
+				String::new(),
+				String::new(),
+				String::new(),
+				vec![],
+				vec![],
+				vec![],
+				vec![],
+				vec![],
+				vec![],
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap_err();
+
+		assert!(matches!(
+			LibraryManagerError::from(err).for_key(key.uuid),
+			LibraryManagerError::KeyAlreadyExists(uuid) if uuid == key.uuid
+		));
+	}
+
+	#[tokio::test]
+	async fn key_exists_after_write() {
+		let (_dir, db) = test_db().await;
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		assert!(!key_exists(&db, key.uuid).await.unwrap());
+
+		write_storedkey_to_db(&db, &key).await.unwrap();
+		assert!(key_exists(&db, key.uuid).await.unwrap());
+
+		soft_delete_storedkey(&db, key.uuid).await.unwrap();
+		assert!(!key_exists(&db, key.uuid).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn count_storedkeys_matches_written_keys() {
+		let (_dir, db) = test_db().await;
+		assert_eq!(count_storedkeys(&db).await.unwrap(), 0);
+
+		let keys = [
+			test_key(Algorithm::XChaCha20Poly1305),
+			test_key(Algorithm::XChaCha20Poly1305),
+			test_key(Algorithm::Aes256Gcm),
+		];
+		write_storedkeys_to_db(&db, &keys).await.unwrap();
+		write_storedkey_to_db(
+			&db,
+			&StoredKey {
+				memory_only: true,
+				..test_key(Algorithm::Aes256Gcm)
+			},
+		)
+		.await
+		.unwrap();
+		assert_eq!(count_storedkeys(&db).await.unwrap(), 3);
+
+		soft_delete_storedkey(&db, keys[0].uuid).await.unwrap();
+		assert_eq!(count_storedkeys(&db).await.unwrap(), 2);
+	}
+
+	#[tokio::test]
+	async fn read_all_storedkeys_skips_corrupt_rows() {
+		let (_dir, db) = test_db().await;
+
+		let keys = [
+			test_key(Algorithm::XChaCha20Poly1305),
+			test_key(Algorithm::Aes256Gcm),
+		];
+		write_storedkeys_to_db(&db, &keys).await.unwrap();
+
+		db.key()
+			.update(
+				key::uuid::equals(keys[1].uuid.to_string()),
+				vec![key::salt::set(vec![0; 3])],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let read = read_all_storedkeys_from_db(&db, false).await.unwrap();
+		assert!(read.keys == [keys[0].clone()]);
+		assert_eq!(read.skipped, 1);
+	}
+}
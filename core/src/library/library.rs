@@ -6,7 +6,11 @@ use crate::{
 		LocationManager,
 	},
 	node::NodeConfigManager,
-	object::{orphan_remover::OrphanRemoverActor, preview::get_thumbnail_path},
+	object::{
+		orphan_remover::OrphanRemoverActor,
+		preview::{get_thumbnail_path, ThumbnailCache, ThumbnailProgressTracker},
+		tag::TagInheritanceGraph,
+	},
 	prisma::{file_path, location, PrismaClient},
 	sync::SyncManager,
 	util::error::FileIOError,
@@ -24,7 +28,7 @@ use tokio::{fs, io};
 use tracing::warn;
 use uuid::Uuid;
 
-use super::{LibraryConfig, LibraryManagerError};
+use super::{LibraryConfig, LibraryManagerError, PrismaKeyStore};
 
 /// LibraryContext holds context for a library which can be passed around the application.
 #[derive(Clone)]
@@ -45,6 +49,12 @@ pub struct Library {
 	/// node_context holds the node context for the node which this library is running on.
 	pub(super) node_context: NodeContext,
 	pub orphan_remover: OrphanRemoverActor,
+	/// tag_inheritance caches the tags that objects inherit from their directories
+	pub tag_inheritance: Arc<TagInheritanceGraph>,
+	/// thumbnail_progress tracks how far the thumbnailer job of this library has gotten
+	pub thumbnail_progress: Arc<ThumbnailProgressTracker>,
+	/// thumbnail_cache is the node's, as every library shares the node's thumbnail directory
+	pub thumbnail_cache: Arc<ThumbnailCache>,
 }
 
 impl Debug for Library {
@@ -76,6 +86,11 @@ impl Library {
 			.await
 	}
 
+	/// key_store returns the [`KeyStore`](super::KeyStore) that persists this library's keys.
+	pub fn key_store(&self) -> PrismaKeyStore {
+		PrismaKeyStore(self.db.clone())
+	}
+
 	pub(crate) fn emit(&self, event: CoreEvent) {
 		if let Err(e) = self.node_context.event_bus_tx.send(event) {
 			warn!("Error sending event to event bus: {e:?}");
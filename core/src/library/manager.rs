@@ -1,9 +1,12 @@
 use crate::{
 	invalidate_query,
-	location::{indexer::rules, LocationManagerError},
+	location::{file_path_helper::FilePathError, indexer::rules, LocationManagerError},
 	node::Platform,
-	object::orphan_remover::OrphanRemoverActor,
-	prisma::{location, node, PrismaClient},
+	object::{
+		orphan_remover::OrphanRemoverActor, preview::ThumbnailProgressTracker,
+		tag::TagInheritanceGraph,
+	},
+	prisma::{key, location, node, PrismaClient},
 	sync::{SyncManager, SyncMessage},
 	util::{
 		db,
@@ -13,10 +16,7 @@ use crate::{
 	NodeContext,
 };
 
-use sd_crypto::{
-	keys::keymanager::{KeyManager, StoredKey},
-	types::{EncryptedKey, Nonce, Salt},
-};
+use sd_crypto::keys::keymanager::KeyManager;
 
 use std::{
 	env,
@@ -30,7 +30,10 @@ use tokio::{fs, io, sync::RwLock, try_join};
 use tracing::{debug, error, warn};
 use uuid::Uuid;
 
-use super::{Library, LibraryConfig, LibraryConfigWrapped};
+use super::{
+	read_all_storedkeys_from_db, spawn_key_expiry_checker, KeyValidationError, Library,
+	LibraryConfig, LibraryConfigWrapped,
+};
 
 /// LibraryManager is a singleton that manages all libraries for a node.
 pub struct LibraryManager {
@@ -46,10 +49,16 @@ pub struct LibraryManager {
 pub enum LibraryManagerError {
 	#[error(transparent)]
 	FileIO(#[from] FileIOError),
+	#[error("invalid path: <lossy_path='{}'>", .0.to_string_lossy())]
+	InvalidPath(PathBuf),
 	#[error("error serializing or deserializing the JSON in the config file")]
 	Json(#[from] serde_json::Error),
 	#[error("database error")]
-	Database(#[from] prisma_client_rust::QueryError),
+	Database(#[source] prisma_client_rust::QueryError),
+	#[error("a key with the uuid '{0}' already exists in the database")]
+	KeyAlreadyExists(Uuid),
+	#[error("database constraint '{constraint}' was violated on table '{table}'")]
+	DatabaseConstraintViolation { table: String, constraint: String },
 	#[error("library not found error")]
 	LibraryNotFound,
 	#[error("error migrating the config file")]
@@ -60,6 +69,28 @@ pub enum LibraryManagerError {
 	IndexerRulesSeeder(#[from] rules::SeederError),
 	#[error("failed to initialise the key manager")]
 	KeyManager(#[from] sd_crypto::Error),
+	#[error("key not found in the database: {0}")]
+	KeyNotFound(Uuid),
+	#[error("error serializing or deserializing the '{field}' column of a key")]
+	KeySerialization {
+		field: &'static str,
+		#[source]
+		source: serde_json::Error,
+	},
+	#[error("key column '{column}' has an invalid length of {len} bytes")]
+	InvalidKeyColumnLength { column: &'static str, len: usize },
+	#[error("the key material of key '{uuid}' doesn't match its checksum")]
+	KeyChecksumMismatch { uuid: Uuid },
+	#[error("the content salt of key '{uuid}' is already used by another key")]
+	DuplicateContentSalt { uuid: Uuid },
+	#[error("invalid key: {0}")]
+	KeyValidation(#[from] KeyValidationError),
+	#[error("failed to decode uuid: {0}")]
+	UuidConversion(#[from] db::UuidConversionError),
+	#[error("the key store backup is malformed")]
+	InvalidKeystoreBackup,
+	#[error("{} key(s) appear more than once in the database", .0.len())]
+	DuplicateKeys(Vec<Uuid>),
 	#[error("failed to run library migrations: {0}")]
 	MigratorError(#[from] MigratorError),
 	#[error("error migrating the library: {0}")]
@@ -70,6 +101,87 @@ pub enum LibraryManagerError {
 	NonUtf8Path(#[from] NonUtf8PathError),
 	#[error("failed to watch locations: {0}")]
 	LocationWatcher(#[from] LocationManagerError),
+	#[error(transparent)]
+	FilePath(#[from] FilePathError),
+	#[error("object not found in the database: {0}")]
+	ObjectNotFound(Uuid),
+	#[error("location not found in the database: {0}")]
+	LocationNotFound(Uuid),
+	#[error("object '{0}' has more than one file path, so it's ambiguous which one to move")]
+	AmbiguousObjectMove(Uuid),
+	#[error("object '{0}' is a directory, which can't be moved as its contents wouldn't be moved with it")]
+	DirectoryObjectMove(Uuid),
+	#[error("a file already exists at <path='{}'>", .0.display())]
+	FileAlreadyExists(PathBuf),
+	#[error("invalid page of keys requested (offset: {offset}, limit: {limit})")]
+	InvalidPagination { offset: i64, limit: i64 },
+	#[error("a row with the pub_id '{pub_id}' already exists in the '{table}' table of the library being merged into")]
+	MergeConflict { table: &'static str, pub_id: String },
+}
+
+impl From<prisma_client_rust::QueryError> for LibraryManagerError {
+	fn from(error: prisma_client_rust::QueryError) -> Self {
+		use prisma_client_rust::prisma_errors::query_engine::{
+			ForeignKeyViolation, UniqueKeyViolation,
+		};
+
+		if !error.is_prisma_error::<UniqueKeyViolation>()
+			&& !error.is_prisma_error::<ForeignKeyViolation>()
+		{
+			return Self::Database(error);
+		}
+
+		let prisma_client_rust::QueryError::Execute(inner) = &error else {
+			return Self::Database(error);
+		};
+
+		let Some(known) = inner.as_known() else {
+			return Self::Database(error);
+		};
+
+		// unique violations report the offending fields as `target`, foreign key ones as `field_name`
+		let constraint = match known
+			.meta
+			.get("target")
+			.or_else(|| known.meta.get("field_name"))
+		{
+			Some(serde_json::Value::Array(fields)) => fields
+				.iter()
+				.filter_map(serde_json::Value::as_str)
+				.collect::<Vec<_>>()
+				.join(", "),
+			Some(serde_json::Value::String(field)) => field.clone(),
+			_ => return Self::Database(error),
+		};
+
+		Self::DatabaseConstraintViolation {
+			table: known
+				.meta
+				.get("modelName")
+				.and_then(serde_json::Value::as_str)
+				.unwrap_or("unknown")
+				.to_string(),
+			constraint,
+		}
+	}
+}
+
+impl LibraryManagerError {
+	/// This turns a violation of the unique `uuid` of a key into [`LibraryManagerError::KeyAlreadyExists`].
+	///
+	/// The database error doesn't include the offending value, so it's up to the caller that was writing the key with `uuid`
+	/// to call this on the errors it gets back.
+	pub(crate) fn for_key(self, uuid: Uuid) -> Self {
+		match self {
+			Self::DatabaseConstraintViolation { table, constraint }
+				// older query engines don't report the model, see the `From<QueryError>` impl
+				if (table.eq_ignore_ascii_case("key") || table == "unknown") && constraint == "uuid" =>
+			{
+				Self::KeyAlreadyExists(uuid)
+			}
+			other => other,
+		}
+	}
 }
 
 impl From<LibraryManagerError> for rspc::Error {
@@ -86,44 +198,16 @@ pub async fn seed_keymanager(
 	client: &PrismaClient,
 	km: &Arc<KeyManager>,
 ) -> Result<(), LibraryManagerError> {
-	let mut default = None;
+	// collect and deserialize the stored keys, leaving out any that are corrupt
+	let stored_keys = read_all_storedkeys_from_db(client, false).await?.keys;
 
-	// collect and serialize the stored keys
-	let stored_keys: Vec<StoredKey> = client
+	let default = client
 		.key()
-		.find_many(vec![])
+		.find_first(vec![key::default::equals(true)])
 		.exec()
 		.await?
-		.iter()
-		.map(|key| {
-			let key = key.clone();
-			let uuid = uuid::Uuid::from_str(&key.uuid).expect("invalid key id in the DB");
-
-			if key.default {
-				default = Some(uuid);
-			}
-
-			Ok(StoredKey {
-				uuid,
-				version: serde_json::from_str(&key.version)
-					.map_err(|_| sd_crypto::Error::Serialization)?,
-				key_type: serde_json::from_str(&key.key_type)
-					.map_err(|_| sd_crypto::Error::Serialization)?,
-				algorithm: serde_json::from_str(&key.algorithm)
-					.map_err(|_| sd_crypto::Error::Serialization)?,
-				content_salt: Salt::try_from(key.content_salt)?,
-				master_key: EncryptedKey::try_from(key.master_key)?,
-				master_key_nonce: Nonce::try_from(key.master_key_nonce)?,
-				key_nonce: Nonce::try_from(key.key_nonce)?,
-				key: key.key,
-				hashing_algorithm: serde_json::from_str(&key.hashing_algorithm)
-					.map_err(|_| sd_crypto::Error::Serialization)?,
-				salt: Salt::try_from(key.salt)?,
-				memory_only: false,
-				automount: key.automount,
-			})
-		})
-		.collect::<Result<Vec<StoredKey>, sd_crypto::Error>>()?;
+		.and_then(|key| Uuid::from_str(&key.uuid).ok())
+		.filter(|uuid| stored_keys.iter().any(|key| key.uuid == *uuid));
 
 	// insert all keys from the DB into the keymanager's keystore
 	km.populate_keystore(stored_keys).await?;
@@ -134,6 +218,25 @@ pub async fn seed_keymanager(
 	Ok(())
 }
 
+/// Reads the id of a library from the filename of its `.sdlibrary` config file
+fn library_id_from_config_path(config_path: &Path) -> Result<Uuid, LibraryManagerError> {
+	let stem = config_path
+		.file_stem()
+		.and_then(|stem| stem.to_str())
+		.ok_or_else(|| LibraryManagerError::InvalidPath(config_path.to_path_buf()))?;
+
+	Ok(Uuid::from_str(stem)?)
+}
+
+/// Builds the sqlite url of a library database. sqlite urls must be valid UTF-8
+fn db_url(db_path: &Path) -> Result<String, LibraryManagerError> {
+	let db_path = db_path
+		.to_str()
+		.ok_or_else(|| LibraryManagerError::NonUtf8Path(NonUtf8PathError(db_path.into())))?;
+
+	Ok(format!("file:{db_path}"))
+}
+
 impl LibraryManager {
 	pub(crate) async fn new(
 		libraries_dir: PathBuf,
@@ -164,13 +267,10 @@ impl LibraryManager {
 					.map(|ext| ext == "sdlibrary")
 					.unwrap_or(false)
 			{
-				let Some(Ok(library_id)) = config_path
-				.file_stem()
-				.and_then(|v| v.to_str().map(Uuid::from_str))
-			else {
-				warn!("Attempted to load library from path '{}' but it has an invalid filename. Skipping...", config_path.display());
+				let Ok(library_id) = library_id_from_config_path(&config_path) else {
+					warn!("Attempted to load library from path '{}' but it has an invalid filename. Skipping...", config_path.display());
 					continue;
-			};
+				};
 
 				let db_path = config_path.with_extension("db");
 				match fs::metadata(&db_path).await {
@@ -191,6 +291,10 @@ impl LibraryManager {
 			}
 		}
 
+		node_context
+			.p2p
+			.update_libraries(libraries.iter().map(|library| library.id).collect());
+
 		let this = Arc::new(Self {
 			libraries: RwLock::new(libraries),
 			libraries_dir,
@@ -237,7 +341,10 @@ impl LibraryManager {
 
 		invalidate_query!(library, "library.list");
 
-		self.libraries.write().await.push(library);
+		let mut libraries = self.libraries.write().await;
+		libraries.push(library);
+		self.advertise_libraries(&libraries);
+
 		Ok(LibraryConfigWrapped { uuid: id, config })
 	}
 
@@ -340,10 +447,18 @@ impl LibraryManager {
 		invalidate_query!(library, "library.list");
 
 		libraries.retain(|l| l.id != id);
+		self.advertise_libraries(&libraries);
 
 		Ok(())
 	}
 
+	/// Tells peers on the local network which libraries this node has, so the ones they share can be synced
+	fn advertise_libraries(&self, libraries: &[Library]) {
+		self.node_context
+			.p2p
+			.update_libraries(libraries.iter().map(|library| library.id).collect());
+	}
+
 	// get_ctx will return the library context for the given library id.
 	pub async fn get_library(&self, library_id: Uuid) -> Option<Library> {
 		self.libraries
@@ -361,14 +476,16 @@ impl LibraryManager {
 		config_path: PathBuf,
 		node_context: NodeContext,
 	) -> Result<Library, LibraryManagerError> {
-		let db_path = db_path.as_ref();
-		let db_url = format!(
-			"file:{}",
-			db_path.as_os_str().to_str().ok_or_else(|| {
-				LibraryManagerError::NonUtf8Path(NonUtf8PathError(db_path.into()))
-			})?
+		let db = Arc::new(
+			db::load_and_migrate_with_opts(
+				&db_url(db_path.as_ref())?,
+				db::MigrateOptions {
+					dev_db_roots: vec![node_context.config.data_directory()],
+					..Default::default()
+				},
+			)
+			.await?,
 		);
-		let db = Arc::new(db::load_and_migrate(&db_url).await?);
 
 		let config = LibraryConfig::load_and_migrate(&config_path, &db).await?;
 
@@ -413,6 +530,8 @@ impl LibraryManager {
 			}
 		});
 
+		spawn_key_expiry_checker(id, &db, node_context.event_bus_tx.clone());
+
 		let library = Library {
 			id,
 			local_id: node_data.id,
@@ -420,6 +539,9 @@ impl LibraryManager {
 			key_manager,
 			sync: Arc::new(sync_manager),
 			orphan_remover: OrphanRemoverActor::spawn(db.clone()),
+			tag_inheritance: Arc::new(TagInheritanceGraph::new()),
+			thumbnail_progress: Arc::new(ThumbnailProgressTracker::new()),
+			thumbnail_cache: node_context.thumbnail_cache.clone(),
 			db,
 			node_local_id: node_data.id,
 			node_context,
@@ -455,3 +577,75 @@ impl LibraryManager {
 		Ok(library)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn library_id_is_read_from_config_filename() {
+		let id = Uuid::new_v4();
+		let path = PathBuf::from("/libraries").join(format!("{id}.sdlibrary"));
+
+		assert_eq!(library_id_from_config_path(&path).unwrap(), id);
+	}
+
+	#[test]
+	fn config_filename_that_is_not_a_uuid_is_rejected() {
+		let path = Path::new("/libraries/not-a-uuid.sdlibrary");
+
+		assert!(matches!(
+			library_id_from_config_path(path),
+			Err(LibraryManagerError::Uuid(_))
+		));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn non_utf8_config_filename_is_rejected() {
+		use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+		let path = Path::new("/libraries").join(OsStr::from_bytes(b"\xff\xfe.sdlibrary"));
+
+		assert!(matches!(
+			library_id_from_config_path(&path),
+			Err(LibraryManagerError::InvalidPath(p)) if p == path
+		));
+	}
+
+	#[test]
+	fn only_key_uuid_violations_become_key_already_exists() {
+		let uuid = Uuid::new_v4();
+		let violation =
+			|table: &str, constraint: &str| LibraryManagerError::DatabaseConstraintViolation {
+				table: table.to_string(),
+				constraint: constraint.to_string(),
+			};
+
+		assert!(matches!(
+			violation("Key", "uuid").for_key(uuid),
+			LibraryManagerError::KeyAlreadyExists(id) if id == uuid
+		));
+		assert!(matches!(
+			violation("Key", "content_salt").for_key(uuid),
+			LibraryManagerError::DatabaseConstraintViolation { .. }
+		));
+		assert!(matches!(
+			violation("Location", "uuid").for_key(uuid),
+			LibraryManagerError::DatabaseConstraintViolation { .. }
+		));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn non_utf8_db_path_is_rejected() {
+		use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+		let db_path = Path::new("/libraries").join(OsStr::from_bytes(b"\xff\xfe.db"));
+
+		assert!(matches!(
+			db_url(&db_path),
+			Err(LibraryManagerError::NonUtf8Path(_))
+		));
+	}
+}
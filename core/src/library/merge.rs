@@ -0,0 +1,1369 @@
+use crate::{
+	prisma::{file_path, key, location, node, object, tag, tag_on_object, PrismaClient, SortOrder},
+	sync::{self, SyncManager},
+};
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::json;
+use uuid::Uuid;
+
+use super::{
+	read_all_storedkeys_from_db, write_storedkeys_to_db, ConflictPolicy, Library,
+	LibraryManagerError,
+};
+
+/// How many rows of a table are merged at a time. Each chunk is looked up with a few queries and written in one batch,
+/// which also keeps the `in` lists of those queries well below SQLite's limit on bound variables.
+const MERGE_CHUNK_SIZE: usize = 250;
+
+/// MergeCounts is what [`merge_libraries`] did with the rows of one table of the secondary library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeCounts {
+	/// Rows that weren't in the primary library yet, and were copied into it
+	pub copied: usize,
+	/// Rows that were already in the primary library (or have the same content as a row that is), and were left alone
+	pub merged: usize,
+	/// Rows that were already in the primary library, and were replaced
+	pub overwritten: usize,
+}
+
+impl MergeCounts {
+	fn count(&mut self, outcome: Outcome) {
+		match outcome {
+			Outcome::Copied => self.copied += 1,
+			Outcome::Merged => self.merged += 1,
+			Outcome::Overwritten => self.overwritten += 1,
+		}
+	}
+}
+
+/// MergeReport describes what [`merge_libraries`] did with each of the tables of the secondary library.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+	pub locations: MergeCounts,
+	pub objects: MergeCounts,
+	pub file_paths: MergeCounts,
+	pub tags: MergeCounts,
+	pub keys: MergeCounts,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Outcome {
+	Copied,
+	Merged,
+	Overwritten,
+}
+
+/// The row of the primary library that a row of the secondary library ended up as
+#[derive(Debug, Clone)]
+struct MergedRow {
+	id: i32,
+	pub_id: Vec<u8>,
+}
+
+/// This copies the locations, objects, file paths, tags and keys of the `secondary` library into the `primary` one.
+///
+/// Rows that have the same `pub_id` (or `uuid` for keys) in both libraries are handled according to `on_conflict`.
+/// Objects whose files have the same content as files in the primary library are merged into the object that's already there.
+/// Files are compared by `integrity_checksum` when both of them have one, and by their sampled `cas_id` otherwise.
+///
+/// Rows are merged [`MERGE_CHUNK_SIZE`] at a time, and each chunk is written on its own through the sync manager, so other nodes
+/// receive the merged rows too. A merge that fails part of the way keeps the chunks it already wrote, and merging again with
+/// `ConflictPolicy::Skip` finishes it. The secondary library is only read from.
+///
+/// Locations keep belonging to the node they were indexed on, which is copied too if the primary library doesn't know it yet,
+/// so that this node doesn't try to watch paths that only exist on the other machine.
+pub async fn merge_libraries(
+	primary: &Library,
+	secondary: &PrismaClient,
+	on_conflict: ConflictPolicy,
+) -> Result<MergeReport, LibraryManagerError> {
+	merge_into(&primary.db, &primary.sync, secondary, on_conflict).await
+}
+
+async fn merge_into(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	secondary: &PrismaClient,
+	on_conflict: ConflictPolicy,
+) -> Result<MergeReport, LibraryManagerError> {
+	let mut report = MergeReport::default();
+
+	let nodes = merge_nodes(db, secondary).await?;
+	let locations = merge_locations(
+		db,
+		sync,
+		secondary,
+		&nodes,
+		on_conflict,
+		&mut report.locations,
+	)
+	.await?;
+	let objects = merge_objects(db, sync, secondary, on_conflict, &mut report.objects).await?;
+	merge_file_paths(
+		db,
+		sync,
+		secondary,
+		&locations,
+		&objects,
+		on_conflict,
+		&mut report.file_paths,
+	)
+	.await?;
+	let tags = merge_tags(db, sync, secondary, on_conflict, &mut report.tags).await?;
+	merge_tags_on_objects(db, secondary, &tags, &objects).await?;
+	merge_keys(db, secondary, on_conflict, &mut report.keys).await?;
+
+	Ok(report)
+}
+
+/// Decides what to do with a row of the secondary library that has the same `pub_id` as `existing` in the primary one
+fn resolve_conflict<T>(
+	existing: Option<T>,
+	table: &'static str,
+	pub_id: &[u8],
+	on_conflict: ConflictPolicy,
+) -> Result<Option<(T, Outcome)>, LibraryManagerError> {
+	match (existing, on_conflict) {
+		(None, _) => Ok(None),
+		(Some(existing), ConflictPolicy::Skip) => Ok(Some((existing, Outcome::Merged))),
+		(Some(existing), ConflictPolicy::Overwrite) => Ok(Some((existing, Outcome::Overwritten))),
+		(Some(_), ConflictPolicy::Fail) => Err(LibraryManagerError::MergeConflict {
+			table,
+			pub_id: Uuid::from_slice(pub_id)
+				.map(|uuid| uuid.to_string())
+				.unwrap_or_else(|_| format!("{pub_id:02x?}")),
+		}),
+	}
+}
+
+/// Nodes are never overwritten, as they're only copied so that copied locations still belong to the node they're on.
+///
+/// A library only knows a handful of nodes, so they're all merged at once. Nodes are local to each library, so they aren't synced.
+async fn merge_nodes(
+	db: &PrismaClient,
+	secondary: &PrismaClient,
+) -> Result<HashMap<i32, MergedRow>, LibraryManagerError> {
+	let nodes = secondary.node().find_many(vec![]).exec().await?;
+	let chunk = chunk_pub_ids(nodes.iter().map(|node| (node.id, &node.pub_id)));
+
+	let existing = node_ids(db, &chunk).await?;
+	let missing = nodes
+		.iter()
+		.filter(|node| !existing.contains_key(&node.pub_id))
+		.map(|node| {
+			node::create_unchecked(
+				node.pub_id.clone(),
+				node.name.clone(),
+				vec![
+					node::platform::set(node.platform),
+					node::version::set(node.version.clone()),
+					node::last_seen::set(node.last_seen),
+					node::timezone::set(node.timezone.clone()),
+					node::date_created::set(node.date_created),
+				],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	if !missing.is_empty() {
+		db.node().create_many(missing).exec().await?;
+	}
+
+	let merged = node_ids(db, &chunk).await?;
+
+	Ok(merged_rows(chunk, &merged).collect())
+}
+
+async fn merge_locations(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	secondary: &PrismaClient,
+	nodes: &HashMap<i32, MergedRow>,
+	on_conflict: ConflictPolicy,
+	counts: &mut MergeCounts,
+) -> Result<HashMap<i32, MergedRow>, LibraryManagerError> {
+	let mut ids = HashMap::new();
+	let mut cursor = 0;
+
+	loop {
+		let locations = secondary
+			.location()
+			.find_many(vec![location::id::gt(cursor)])
+			.order_by(location::id::order(SortOrder::Asc))
+			.take(MERGE_CHUNK_SIZE as i64)
+			.exec()
+			.await?;
+		let Some(last) = locations.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		let chunk = chunk_pub_ids(
+			locations
+				.iter()
+				.map(|location| (location.id, &location.pub_id)),
+		);
+		let existing = location_ids(db, &chunk).await?;
+
+		let (mut create_ops, mut creates) = (vec![], vec![]);
+		let (mut update_ops, mut updates) = (vec![], vec![]);
+
+		for location in locations {
+			let Some(node) = nodes.get(&location.node_id) else {
+				continue;
+			};
+
+			let sync_id = || sync::location::SyncId {
+				pub_id: location.pub_id.clone(),
+			};
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					(
+						location::total_capacity::NAME,
+						json!(location.total_capacity),
+					),
+					location::total_capacity::set(location.total_capacity),
+				),
+				(
+					(
+						location::available_capacity::NAME,
+						json!(location.available_capacity),
+					),
+					location::available_capacity::set(location.available_capacity),
+				),
+				(
+					(location::is_archived::NAME, json!(location.is_archived)),
+					location::is_archived::set(location.is_archived),
+				),
+				(
+					(
+						location::generate_preview_media::NAME,
+						json!(location.generate_preview_media),
+					),
+					location::generate_preview_media::set(location.generate_preview_media),
+				),
+				(
+					(
+						location::sync_preview_media::NAME,
+						json!(location.sync_preview_media),
+					),
+					location::sync_preview_media::set(location.sync_preview_media),
+				),
+				(
+					(location::hidden::NAME, json!(location.hidden)),
+					location::hidden::set(location.hidden),
+				),
+				(
+					(location::date_created::NAME, json!(location.date_created)),
+					location::date_created::set(location.date_created),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			match resolve_conflict(
+				existing.get(&location.pub_id).copied(),
+				"location",
+				&location.pub_id,
+				on_conflict,
+			)? {
+				Some((id, Outcome::Overwritten)) => {
+					update_ops.extend(
+						sync_params
+							.into_iter()
+							.chain([
+								(location::name::NAME, json!(&location.name)),
+								(location::path::NAME, json!(&location.path)),
+							])
+							.map(|(field, value)| sync.shared_update(sync_id(), field, value)),
+					);
+					updates.push(
+						db.location().update(
+							location::id::equals(id),
+							db_params
+								.into_iter()
+								.chain([
+									location::name::set(location.name.clone()),
+									location::path::set(location.path.clone()),
+								])
+								.collect(),
+						),
+					);
+					counts.count(Outcome::Overwritten);
+				}
+				Some((_, outcome)) => counts.count(outcome),
+				None => {
+					create_ops.push(
+						sync.unique_shared_create(
+							sync_id(),
+							[
+								(
+									location::node::NAME,
+									json!(sync::node::SyncId {
+										pub_id: node.pub_id.clone()
+									}),
+								),
+								(location::name::NAME, json!(&location.name)),
+								(location::path::NAME, json!(&location.path)),
+							]
+							.into_iter()
+							.chain(sync_params),
+						),
+					);
+					creates.push(location::create_unchecked(
+						location.pub_id.clone(),
+						node.id,
+						location.name,
+						location.path,
+						db_params,
+					));
+					counts.count(Outcome::Copied);
+				}
+			}
+		}
+
+		if !creates.is_empty() {
+			sync.write_ops(db, (create_ops, db.location().create_many(creates)))
+				.await?;
+		}
+		if !updates.is_empty() {
+			sync.write_ops(db, (update_ops, updates)).await?;
+		}
+
+		let merged = location_ids(db, &chunk).await?;
+		ids.extend(merged_rows(chunk, &merged));
+	}
+
+	Ok(ids)
+}
+/// This finds the objects of the primary library that have the same content as the `objects` of the secondary one.
+///
+/// A file's `integrity_checksum` hashes all of its bytes, so files that have the same one always have the same content.
+/// The `cas_id` only samples the file, so files that have the same `cas_id` are only the same if they don't both have an
+/// `integrity_checksum`, or both have the same one.
+async fn same_content_objects(
+	db: &PrismaClient,
+	secondary: &PrismaClient,
+	objects: &[object::Data],
+) -> Result<HashMap<i32, MergedRow>, LibraryManagerError> {
+	let file_paths = secondary
+		.file_path()
+		.find_many(vec![file_path::object_id::in_vec(
+			objects.iter().map(|object| object.id).collect(),
+		)])
+		.select(file_path::select!({ object_id cas_id integrity_checksum }))
+		.exec()
+		.await?;
+
+	let checksums = file_paths
+		.iter()
+		.filter_map(|file_path| file_path.integrity_checksum.clone())
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>();
+	let cas_ids = file_paths
+		.iter()
+		.filter_map(|file_path| file_path.cas_id.clone())
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>();
+
+	let mut by_checksum = HashMap::new();
+	for checksums in checksums.chunks(MERGE_CHUNK_SIZE) {
+		for file_path in db
+			.file_path()
+			.find_many(vec![
+				file_path::integrity_checksum::in_vec(checksums.to_vec()),
+				file_path::object_id::not(None),
+			])
+			.select(file_path::select!({ integrity_checksum object: select { id pub_id } }))
+			.exec()
+			.await?
+		{
+			if let (Some(checksum), Some(object)) = (file_path.integrity_checksum, file_path.object)
+			{
+				by_checksum.insert(
+					checksum,
+					MergedRow {
+						id: object.id,
+						pub_id: object.pub_id,
+					},
+				);
+			}
+		}
+	}
+
+	let mut by_cas_id = HashMap::<_, Vec<_>>::new();
+	for cas_ids in cas_ids.chunks(MERGE_CHUNK_SIZE) {
+		for file_path in db
+			.file_path()
+			.find_many(vec![
+				file_path::cas_id::in_vec(cas_ids.to_vec()),
+				file_path::object_id::not(None),
+			])
+			.select(file_path::select!({ cas_id integrity_checksum object: select { id pub_id } }))
+			.exec()
+			.await?
+		{
+			if let (Some(cas_id), Some(object)) = (file_path.cas_id, file_path.object) {
+				by_cas_id.entry(cas_id).or_default().push((
+					file_path.integrity_checksum,
+					MergedRow {
+						id: object.id,
+						pub_id: object.pub_id,
+					},
+				));
+			}
+		}
+	}
+
+	let mut same_content = HashMap::new();
+	for file_path in file_paths {
+		let Some(object_id) = file_path.object_id else {
+			continue;
+		};
+		if same_content.contains_key(&object_id) {
+			continue;
+		}
+
+		let checksum_match = file_path
+			.integrity_checksum
+			.as_ref()
+			.and_then(|checksum| by_checksum.get(checksum));
+		let cas_id_match = || {
+			file_path
+				.cas_id
+				.as_ref()
+				.and_then(|cas_id| by_cas_id.get(cas_id))
+				.and_then(|candidates| {
+					candidates.iter().find_map(|(checksum, row)| {
+						match (checksum, &file_path.integrity_checksum) {
+							(Some(checksum), Some(other)) if checksum != other => None,
+							_ => Some(row),
+						}
+					})
+				})
+		};
+
+		if let Some(row) = checksum_match.or_else(cas_id_match) {
+			same_content.insert(object_id, row.clone());
+		}
+	}
+
+	Ok(same_content)
+}
+
+async fn merge_objects(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	secondary: &PrismaClient,
+	on_conflict: ConflictPolicy,
+	counts: &mut MergeCounts,
+) -> Result<HashMap<i32, MergedRow>, LibraryManagerError> {
+	let mut ids = HashMap::new();
+	let mut cursor = 0;
+
+	loop {
+		let objects = secondary
+			.object()
+			.find_many(vec![object::id::gt(cursor)])
+			.order_by(object::id::order(SortOrder::Asc))
+			.take(MERGE_CHUNK_SIZE as i64)
+			.exec()
+			.await?;
+		let Some(last) = objects.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		let chunk = chunk_pub_ids(objects.iter().map(|object| (object.id, &object.pub_id)));
+		let existing = object_ids(db, &chunk).await?;
+		let mut same_content = same_content_objects(db, secondary, &objects).await?;
+
+		let (mut create_ops, mut creates) = (vec![], vec![]);
+		let (mut update_ops, mut updates) = (vec![], vec![]);
+
+		for object in objects {
+			let sync_id = || sync::object::SyncId {
+				pub_id: object.pub_id.clone(),
+			};
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					(object::kind::NAME, json!(object.kind)),
+					object::kind::set(object.kind),
+				),
+				(
+					(object::hidden::NAME, json!(object.hidden)),
+					object::hidden::set(object.hidden),
+				),
+				(
+					(object::favorite::NAME, json!(object.favorite)),
+					object::favorite::set(object.favorite),
+				),
+				(
+					(object::important::NAME, json!(object.important)),
+					object::important::set(object.important),
+				),
+				(
+					(object::note::NAME, json!(&object.note)),
+					object::note::set(object.note.clone()),
+				),
+				(
+					(object::date_created::NAME, json!(object.date_created)),
+					object::date_created::set(object.date_created),
+				),
+				(
+					(object::date_accessed::NAME, json!(object.date_accessed)),
+					object::date_accessed::set(object.date_accessed),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			let sync_updates = |sync_params: Vec<_>| {
+				sync_params
+					.into_iter()
+					.map(|(field, value)| sync.shared_update(sync_id(), field, value))
+					.collect::<Vec<_>>()
+			};
+
+			match resolve_conflict(
+				existing.get(&object.pub_id).copied(),
+				"object",
+				&object.pub_id,
+				on_conflict,
+			)? {
+				Some((id, Outcome::Overwritten)) => {
+					update_ops.extend(sync_updates(sync_params));
+					updates.push(db.object().update(object::id::equals(id), db_params));
+					counts.count(Outcome::Overwritten);
+				}
+				Some((_, outcome)) => counts.count(outcome),
+				None => match same_content.remove(&object.id) {
+					Some(row) => {
+						ids.insert(object.id, row);
+						counts.count(Outcome::Merged);
+					}
+					None => {
+						create_ops.push(sync.shared_create(sync_id()));
+						create_ops.extend(sync_updates(sync_params));
+						creates.push(object::create_unchecked(object.pub_id.clone(), db_params));
+						counts.count(Outcome::Copied);
+					}
+				},
+			}
+		}
+
+		if !creates.is_empty() {
+			sync.write_ops(db, (create_ops, db.object().create_many(creates)))
+				.await?;
+		}
+		if !updates.is_empty() {
+			sync.write_ops(db, (update_ops, updates)).await?;
+		}
+
+		let merged = object_ids(db, &chunk).await?;
+		ids.extend(merged_rows(chunk, &merged));
+	}
+
+	Ok(ids)
+}
+
+async fn merge_file_paths(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	secondary: &PrismaClient,
+	locations: &HashMap<i32, MergedRow>,
+	objects: &HashMap<i32, MergedRow>,
+	on_conflict: ConflictPolicy,
+	counts: &mut MergeCounts,
+) -> Result<(), LibraryManagerError> {
+	let mut cursor = 0;
+
+	loop {
+		let file_paths = secondary
+			.file_path()
+			.find_many(vec![file_path::id::gt(cursor)])
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(MERGE_CHUNK_SIZE as i64)
+			.exec()
+			.await?;
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		// file paths of locations that weren't merged are left out, like the locations themselves
+		let file_paths = file_paths
+			.into_iter()
+			.filter_map(|file_path| {
+				locations
+					.get(&file_path.location_id)
+					.map(|location| (location, file_path))
+			})
+			.collect::<Vec<_>>();
+		if file_paths.is_empty() {
+			continue;
+		}
+
+		let chunk = chunk_pub_ids(
+			file_paths
+				.iter()
+				.map(|(_, file_path)| (file_path.id, &file_path.pub_id)),
+		);
+		let existing = file_path_ids(db, &chunk).await?;
+
+		// a file path with another pub_id can already be at the same place, e.g. if the location was indexed by both libraries
+		let location_ids = file_paths
+			.iter()
+			.map(|(location, _)| location.id)
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.collect::<Vec<_>>();
+		let same_inode = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::in_vec(location_ids.clone()),
+				file_path::inode::in_vec(
+					file_paths
+						.iter()
+						.map(|(_, file_path)| file_path.inode.clone())
+						.collect(),
+				),
+			])
+			.select(file_path::select!({ location_id inode device }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| (file_path.location_id, file_path.inode, file_path.device))
+			.collect::<HashSet<_>>();
+		// extensions are compared case-insensitively by SQLite, see the migration
+		let same_path = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::in_vec(location_ids),
+				file_path::name::in_vec(
+					file_paths
+						.iter()
+						.map(|(_, file_path)| file_path.name.clone())
+						.collect(),
+				),
+			])
+			.select(file_path::select!({ location_id materialized_path name extension }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| {
+				(
+					file_path.location_id,
+					file_path.materialized_path,
+					file_path.name,
+					file_path.extension.to_lowercase(),
+				)
+			})
+			.collect::<HashSet<_>>();
+
+		// integrity checksums are unique, so ones that another file path already has aren't copied
+		let checksums = db
+			.file_path()
+			.find_many(vec![file_path::integrity_checksum::in_vec(
+				file_paths
+					.iter()
+					.filter_map(|(_, file_path)| file_path.integrity_checksum.clone())
+					.collect(),
+			)])
+			.select(file_path::select!({ id integrity_checksum }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| {
+				file_path
+					.integrity_checksum
+					.map(|checksum| (checksum, file_path.id))
+			})
+			.collect::<HashMap<_, _>>();
+
+		let (mut create_ops, mut creates) = (vec![], vec![]);
+		let (mut update_ops, mut updates) = (vec![], vec![]);
+
+		for (location, file_path) in file_paths {
+			let resolution = resolve_conflict(
+				existing.get(&file_path.pub_id).copied(),
+				"file_path",
+				&file_path.pub_id,
+				on_conflict,
+			)?;
+
+			if resolution.is_none()
+				&& (same_inode.contains(&(
+					location.id,
+					file_path.inode.clone(),
+					file_path.device.clone(),
+				)) || same_path.contains(&(
+					location.id,
+					file_path.materialized_path.clone(),
+					file_path.name.clone(),
+					file_path.extension.to_lowercase(),
+				))) {
+				counts.count(Outcome::Merged);
+				continue;
+			}
+
+			let id = resolution.map(|(id, _)| id);
+			let integrity_checksum = file_path.integrity_checksum.clone().filter(|checksum| {
+				checksums
+					.get(checksum)
+					.map_or(true, |&owner| Some(owner) == id)
+			});
+			let object = file_path
+				.object_id
+				.and_then(|object_id| objects.get(&object_id));
+
+			let sync_id = || sync::file_path::SyncId {
+				pub_id: file_path.pub_id.clone(),
+			};
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					(file_path::is_dir::NAME, json!(file_path.is_dir)),
+					file_path::is_dir::set(file_path.is_dir),
+				),
+				(
+					(file_path::cas_id::NAME, json!(&file_path.cas_id)),
+					file_path::cas_id::set(file_path.cas_id.clone()),
+				),
+				(
+					(
+						file_path::integrity_checksum::NAME,
+						json!(&integrity_checksum),
+					),
+					file_path::integrity_checksum::set(integrity_checksum.clone()),
+				),
+				(
+					(
+						file_path::size_in_bytes::NAME,
+						json!(&file_path.size_in_bytes),
+					),
+					file_path::size_in_bytes::set(file_path.size_in_bytes.clone()),
+				),
+				(
+					(
+						file_path::object::NAME,
+						json!(object.map(|object| sync::object::SyncId {
+							pub_id: object.pub_id.clone()
+						})),
+					),
+					file_path::object_id::set(object.map(|object| object.id)),
+				),
+				(
+					(file_path::date_created::NAME, json!(file_path.date_created)),
+					file_path::date_created::set(file_path.date_created),
+				),
+				(
+					(
+						file_path::date_modified::NAME,
+						json!(file_path.date_modified),
+					),
+					file_path::date_modified::set(file_path.date_modified),
+				),
+				(
+					(file_path::date_indexed::NAME, json!(file_path.date_indexed)),
+					file_path::date_indexed::set(file_path.date_indexed),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			match resolution {
+				Some((id, Outcome::Overwritten)) => {
+					update_ops.extend(
+						sync_params
+							.into_iter()
+							.map(|(field, value)| sync.shared_update(sync_id(), field, value)),
+					);
+					updates.push(db.file_path().update(file_path::id::equals(id), db_params));
+					counts.count(Outcome::Overwritten);
+				}
+				Some((_, outcome)) => counts.count(outcome),
+				None => {
+					create_ops.push(
+						sync.unique_shared_create(
+							sync_id(),
+							[
+								(
+									file_path::location::NAME,
+									json!(sync::location::SyncId {
+										pub_id: location.pub_id.clone()
+									}),
+								),
+								(
+									file_path::materialized_path::NAME,
+									json!(&file_path.materialized_path),
+								),
+								(file_path::name::NAME, json!(&file_path.name)),
+								(file_path::extension::NAME, json!(&file_path.extension)),
+								(file_path::inode::NAME, json!(&file_path.inode)),
+								(file_path::device::NAME, json!(&file_path.device)),
+							]
+							.into_iter()
+							.chain(sync_params),
+						),
+					);
+					creates.push(file_path::create_unchecked(
+						file_path.pub_id,
+						location.id,
+						file_path.materialized_path,
+						file_path.name,
+						file_path.extension,
+						file_path.inode,
+						file_path.device,
+						db_params,
+					));
+					counts.count(Outcome::Copied);
+				}
+			}
+		}
+
+		if !creates.is_empty() {
+			sync.write_ops(db, (create_ops, db.file_path().create_many(creates)))
+				.await?;
+		}
+		if !updates.is_empty() {
+			sync.write_ops(db, (update_ops, updates)).await?;
+		}
+	}
+
+	Ok(())
+}
+
+async fn merge_tags(
+	db: &PrismaClient,
+	sync: &SyncManager,
+	secondary: &PrismaClient,
+	on_conflict: ConflictPolicy,
+	counts: &mut MergeCounts,
+) -> Result<HashMap<i32, MergedRow>, LibraryManagerError> {
+	let mut ids = HashMap::new();
+	let mut cursor = 0;
+
+	loop {
+		let tags = secondary
+			.tag()
+			.find_many(vec![tag::id::gt(cursor)])
+			.order_by(tag::id::order(SortOrder::Asc))
+			.take(MERGE_CHUNK_SIZE as i64)
+			.exec()
+			.await?;
+		let Some(last) = tags.last() else {
+			break;
+		};
+		cursor = last.id;
+
+		let chunk = chunk_pub_ids(tags.iter().map(|tag| (tag.id, &tag.pub_id)));
+		let existing = tag_ids(db, &chunk).await?;
+
+		let (mut create_ops, mut creates) = (vec![], vec![]);
+		let (mut update_ops, mut updates) = (vec![], vec![]);
+
+		for tag in tags {
+			let sync_id = || sync::tag::SyncId {
+				pub_id: tag.pub_id.clone(),
+			};
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					(tag::name::NAME, json!(&tag.name)),
+					tag::name::set(tag.name.clone()),
+				),
+				(
+					(tag::color::NAME, json!(&tag.color)),
+					tag::color::set(tag.color.clone()),
+				),
+				(
+					(tag::redundancy_goal::NAME, json!(tag.redundancy_goal)),
+					tag::redundancy_goal::set(tag.redundancy_goal),
+				),
+				(
+					(tag::date_created::NAME, json!(tag.date_created)),
+					tag::date_created::set(tag.date_created),
+				),
+				(
+					(tag::date_modified::NAME, json!(tag.date_modified)),
+					tag::date_modified::set(tag.date_modified),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			match resolve_conflict(
+				existing.get(&tag.pub_id).copied(),
+				"tag",
+				&tag.pub_id,
+				on_conflict,
+			)? {
+				Some((id, Outcome::Overwritten)) => {
+					update_ops.extend(
+						sync_params
+							.into_iter()
+							.map(|(field, value)| sync.shared_update(sync_id(), field, value)),
+					);
+					updates.push(db.tag().update(tag::id::equals(id), db_params));
+					counts.count(Outcome::Overwritten);
+				}
+				Some((_, outcome)) => counts.count(outcome),
+				None => {
+					create_ops.push(sync.unique_shared_create(sync_id(), sync_params));
+					creates.push(tag::create_unchecked(tag.pub_id, db_params));
+					counts.count(Outcome::Copied);
+				}
+			}
+		}
+
+		if !creates.is_empty() {
+			sync.write_ops(db, (create_ops, db.tag().create_many(creates)))
+				.await?;
+		}
+		if !updates.is_empty() {
+			sync.write_ops(db, (update_ops, updates)).await?;
+		}
+
+		let merged = tag_ids(db, &chunk).await?;
+		ids.extend(merged_rows(chunk, &merged));
+	}
+
+	Ok(ids)
+}
+
+/// Tags are assigned to objects without the sync manager (see `tags.assign`), so merged assignments aren't synced either
+async fn merge_tags_on_objects(
+	db: &PrismaClient,
+	secondary: &PrismaClient,
+	tags: &HashMap<i32, MergedRow>,
+	objects: &HashMap<i32, MergedRow>,
+) -> Result<(), LibraryManagerError> {
+	let mut skip = 0;
+
+	loop {
+		let tags_on_objects = secondary
+			.tag_on_object()
+			.find_many(vec![])
+			.order_by(tag_on_object::tag_id::order(SortOrder::Asc))
+			.order_by(tag_on_object::object_id::order(SortOrder::Asc))
+			.skip(skip)
+			.take(MERGE_CHUNK_SIZE as i64)
+			.exec()
+			.await?;
+		if tags_on_objects.is_empty() {
+			break;
+		}
+		skip += tags_on_objects.len() as i64;
+
+		let creates = tags_on_objects
+			.iter()
+			.filter_map(|tag_on_object| {
+				let tag = tags.get(&tag_on_object.tag_id)?;
+				let object = objects.get(&tag_on_object.object_id)?;
+
+				Some(tag_on_object::create_unchecked(tag.id, object.id, vec![]))
+			})
+			.collect::<Vec<_>>();
+
+		// objects with the same content are merged into one, so they can already have the tag
+		if !creates.is_empty() {
+			db.tag_on_object()
+				.create_many(creates)
+				.skip_duplicates()
+				.exec()
+				.await?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Keys are written [`MERGE_CHUNK_SIZE`] at a time, each chunk in its own transaction through [`write_storedkeys_to_db`]
+async fn merge_keys(
+	db: &PrismaClient,
+	secondary: &PrismaClient,
+	on_conflict: ConflictPolicy,
+	counts: &mut MergeCounts,
+) -> Result<(), LibraryManagerError> {
+	let keys = read_all_storedkeys_from_db(secondary, false)
+		.await?
+		.keys
+		.into_iter()
+		.filter(|key| !key.memory_only)
+		.collect::<Vec<_>>();
+
+	for chunk in keys.chunks(MERGE_CHUNK_SIZE) {
+		// soft-deleted keys count as conflicts too, as writing them would bring them back
+		let existing = db
+			.key()
+			.find_many(vec![key::uuid::in_vec(
+				chunk.iter().map(|key| key.uuid.to_string()).collect(),
+			)])
+			.select(key::select!({ uuid }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|key| key.uuid)
+			.collect::<HashSet<_>>();
+
+		let mut writes = Vec::with_capacity(chunk.len());
+		for key in chunk {
+			let outcome = match (existing.contains(&key.uuid.to_string()), on_conflict) {
+				(false, _) => Outcome::Copied,
+				(true, ConflictPolicy::Skip) => {
+					counts.count(Outcome::Merged);
+					continue;
+				}
+				(true, ConflictPolicy::Overwrite) => Outcome::Overwritten,
+				(true, ConflictPolicy::Fail) => {
+					return Err(LibraryManagerError::KeyAlreadyExists(key.uuid))
+				}
+			};
+
+			writes.push(key.clone());
+			counts.count(outcome);
+		}
+
+		write_storedkeys_to_db(db, &writes).await?;
+	}
+
+	Ok(())
+}
+
+/// Pairs the ids of a chunk of rows of the secondary library with their `pub_id`s
+fn chunk_pub_ids<'a>(rows: impl Iterator<Item = (i32, &'a Vec<u8>)>) -> Vec<(i32, Vec<u8>)> {
+	rows.map(|(id, pub_id)| (id, pub_id.clone())).collect()
+}
+
+fn pub_ids(chunk: &[(i32, Vec<u8>)]) -> Vec<Vec<u8>> {
+	chunk.iter().map(|(_, pub_id)| pub_id.clone()).collect()
+}
+
+/// Maps the rows of a chunk of the secondary library to the rows of the primary library that have the same `pub_id`
+fn merged_rows(
+	chunk: Vec<(i32, Vec<u8>)>,
+	merged: &HashMap<Vec<u8>, i32>,
+) -> impl Iterator<Item = (i32, MergedRow)> + '_ {
+	chunk.into_iter().filter_map(|(id, pub_id)| {
+		merged.get(&pub_id).map(|&merged_id| {
+			(
+				id,
+				MergedRow {
+					id: merged_id,
+					pub_id,
+				},
+			)
+		})
+	})
+}
+
+/// The ids in the primary library of the nodes of `chunk`, by `pub_id`
+async fn node_ids(
+	db: &PrismaClient,
+	chunk: &[(i32, Vec<u8>)],
+) -> Result<HashMap<Vec<u8>, i32>, LibraryManagerError> {
+	Ok(db
+		.node()
+		.find_many(vec![node::pub_id::in_vec(pub_ids(chunk))])
+		.select(node::select!({ id pub_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|node| (node.pub_id, node.id))
+		.collect())
+}
+
+/// The ids in the primary library of the locations of `chunk`, by `pub_id`
+async fn location_ids(
+	db: &PrismaClient,
+	chunk: &[(i32, Vec<u8>)],
+) -> Result<HashMap<Vec<u8>, i32>, LibraryManagerError> {
+	Ok(db
+		.location()
+		.find_many(vec![location::pub_id::in_vec(pub_ids(chunk))])
+		.select(location::select!({ id pub_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|location| (location.pub_id, location.id))
+		.collect())
+}
+
+/// The ids in the primary library of the objects of `chunk`, by `pub_id`
+async fn object_ids(
+	db: &PrismaClient,
+	chunk: &[(i32, Vec<u8>)],
+) -> Result<HashMap<Vec<u8>, i32>, LibraryManagerError> {
+	Ok(db
+		.object()
+		.find_many(vec![object::pub_id::in_vec(pub_ids(chunk))])
+		.select(object::select!({ id pub_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|object| (object.pub_id, object.id))
+		.collect())
+}
+
+/// The ids in the primary library of the file paths of `chunk`, by `pub_id`
+async fn file_path_ids(
+	db: &PrismaClient,
+	chunk: &[(i32, Vec<u8>)],
+) -> Result<HashMap<Vec<u8>, i32>, LibraryManagerError> {
+	Ok(db
+		.file_path()
+		.find_many(vec![file_path::pub_id::in_vec(pub_ids(chunk))])
+		.select(file_path::select!({ id pub_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.pub_id, file_path.id))
+		.collect())
+}
+
+/// The ids in the primary library of the tags of `chunk`, by `pub_id`
+async fn tag_ids(
+	db: &PrismaClient,
+	chunk: &[(i32, Vec<u8>)],
+) -> Result<HashMap<Vec<u8>, i32>, LibraryManagerError> {
+	Ok(db
+		.tag()
+		.find_many(vec![tag::pub_id::in_vec(pub_ids(chunk))])
+		.select(tag::select!({ id pub_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|tag| (tag.pub_id, tag.id))
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::{
+		library::{key_exists, soft_delete_storedkey, test_key, write_storedkey_to_db},
+		util::db::{test_db, uuid_to_bytes},
+	};
+
+	use std::sync::Arc;
+
+	use sd_crypto::types::Algorithm;
+	use tempfile::TempDir;
+
+	async fn library() -> (TempDir, Arc<PrismaClient>) {
+		let (dir, db) = test_db().await;
+
+		(dir, Arc::new(db))
+	}
+
+	/// The sync manager of `db`, whose node has to exist for the operations it writes
+	async fn sync_manager(db: &Arc<PrismaClient>) -> SyncManager {
+		let node = Uuid::new_v4();
+		db.node()
+			.create(uuid_to_bytes(node), "primary".to_string(), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		SyncManager::new(db, node).0
+	}
+
+	async fn file_with_content(
+		db: &PrismaClient,
+		location_id: i32,
+		inode: u64,
+		cas_id: &str,
+		integrity_checksum: Option<&str>,
+	) -> i32 {
+		let object = db
+			.object()
+			.create(uuid_to_bytes(Uuid::new_v4()), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		db.file_path()
+			.create_unchecked(
+				uuid_to_bytes(Uuid::new_v4()),
+				location_id,
+				"/".to_string(),
+				format!("file-{inode}"),
+				"txt".to_string(),
+				inode.to_le_bytes().to_vec(),
+				vec![0; 8],
+				vec![
+					file_path::cas_id::set(Some(cas_id.to_string())),
+					file_path::integrity_checksum::set(integrity_checksum.map(str::to_string)),
+					file_path::object_id::set(Some(object.id)),
+				],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		object.id
+	}
+
+	async fn location(db: &PrismaClient, node_pub_id: Vec<u8>) -> i32 {
+		let node = db
+			.node()
+			.upsert(
+				node::pub_id::equals(node_pub_id.clone()),
+				node::create(node_pub_id, "node".to_string(), vec![]),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		db.location()
+			.create_unchecked(
+				uuid_to_bytes(Uuid::new_v4()),
+				node.id,
+				"location".to_string(),
+				"/location".to_string(),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap()
+			.id
+	}
+
+	#[tokio::test]
+	async fn merge_libraries_copies_tags_and_resolves_conflicts() {
+		let (_primary_dir, primary) = library().await;
+		let (_secondary_dir, secondary) = library().await;
+		let sync = sync_manager(&primary).await;
+
+		let shared = uuid_to_bytes(Uuid::new_v4());
+		for (db, name) in [(&primary, "primary"), (&secondary, "secondary")] {
+			db.tag()
+				.create(shared.clone(), vec![tag::name::set(Some(name.to_string()))])
+				.exec()
+				.await
+				.unwrap();
+		}
+		secondary
+			.tag()
+			.create(uuid_to_bytes(Uuid::new_v4()), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		assert!(matches!(
+			merge_into(&primary, &sync, &secondary, ConflictPolicy::Fail).await,
+			Err(LibraryManagerError::MergeConflict { table: "tag", .. })
+		));
+		assert_eq!(primary.tag().count(vec![]).exec().await.unwrap(), 1);
+
+		let report = merge_into(&primary, &sync, &secondary, ConflictPolicy::Skip)
+			.await
+			.unwrap();
+		assert_eq!(
+			report.tags,
+			MergeCounts {
+				copied: 1,
+				merged: 1,
+				overwritten: 0
+			}
+		);
+
+		let report = merge_into(&primary, &sync, &secondary, ConflictPolicy::Overwrite)
+			.await
+			.unwrap();
+		assert_eq!(report.tags.overwritten, 2);
+		assert_eq!(
+			primary
+				.tag()
+				.find_unique(tag::pub_id::equals(shared))
+				.exec()
+				.await
+				.unwrap()
+				.unwrap()
+				.name
+				.as_deref(),
+			Some("secondary")
+		);
+	}
+
+	#[tokio::test]
+	async fn objects_are_merged_by_integrity_checksum_before_cas_id() {
+		let (_primary_dir, primary) = library().await;
+		let (_secondary_dir, secondary) = library().await;
+		let sync = sync_manager(&primary).await;
+
+		let node = uuid_to_bytes(Uuid::new_v4());
+		let primary_location = location(&primary, node.clone()).await;
+		let existing = file_with_content(&primary, primary_location, 1, "x", Some("a")).await;
+
+		let secondary_location = location(&secondary, node).await;
+		// the same bytes, even though the sampled cas_id differs
+		file_with_content(&secondary, secondary_location, 1, "y", Some("a")).await;
+		// the same cas_id, but the full checksums tell the files apart
+		file_with_content(&secondary, secondary_location, 2, "x", Some("b")).await;
+		// the same cas_id, and there's no full checksum to tell otherwise
+		file_with_content(&secondary, secondary_location, 3, "x", None).await;
+
+		let report = merge_into(&primary, &sync, &secondary, ConflictPolicy::Skip)
+			.await
+			.unwrap();
+		assert_eq!(
+			report.objects,
+			MergeCounts {
+				copied: 1,
+				merged: 2,
+				overwritten: 0
+			}
+		);
+		assert_eq!(report.file_paths.copied, 3);
+		assert_eq!(primary.object().count(vec![]).exec().await.unwrap(), 2);
+		assert_eq!(
+			primary
+				.file_path()
+				.count(vec![file_path::object_id::equals(Some(existing))])
+				.exec()
+				.await
+				.unwrap(),
+			3
+		);
+	}
+
+	#[tokio::test]
+	async fn keys_deleted_from_the_primary_library_are_conflicts() {
+		let (_primary_dir, primary) = library().await;
+		let (_secondary_dir, secondary) = library().await;
+		let sync = sync_manager(&primary).await;
+
+		let key = test_key(Algorithm::XChaCha20Poly1305);
+		for db in [&primary, &secondary] {
+			write_storedkey_to_db(db, &key).await.unwrap();
+		}
+		soft_delete_storedkey(&primary, key.uuid).await.unwrap();
+
+		assert!(matches!(
+			merge_into(&primary, &sync, &secondary, ConflictPolicy::Fail).await,
+			Err(LibraryManagerError::KeyAlreadyExists(uuid)) if uuid == key.uuid
+		));
+
+		let report = merge_into(&primary, &sync, &secondary, ConflictPolicy::Skip)
+			.await
+			.unwrap();
+		assert_eq!(report.keys.merged, 1);
+		assert!(!key_exists(&primary, key.uuid).await.unwrap());
+
+		let report = merge_into(&primary, &sync, &secondary, ConflictPolicy::Overwrite)
+			.await
+			.unwrap();
+		assert_eq!(report.keys.overwritten, 1);
+		assert!(key_exists(&primary, key.uuid).await.unwrap());
+	}
+}
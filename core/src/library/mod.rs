@@ -1,10 +1,18 @@
 pub(crate) mod cat;
 mod config;
+mod key_expiry;
+mod key_store;
+mod keys;
 #[allow(clippy::module_inception)]
 mod library;
 mod manager;
+mod merge;
 
 pub use cat::*;
 pub use config::*;
+pub use key_expiry::*;
+pub use key_store::*;
+pub use keys::*;
 pub use library::*;
 pub use manager::*;
+pub use merge::*;
@@ -162,10 +162,17 @@ impl StatefulJob for IndexerJob {
 			total_save_steps: state.steps.len() as u64 - to_walk_count as u64,
 		});
 
+		let errors = errors
+			.into_iter()
+			.map(|e| format!("{e}"))
+			.collect::<Vec<_>>();
+
+		if let Some(data) = &state.data {
+			data.emit_report(&ctx, &state.init.location, &errors);
+		}
+
 		if !errors.is_empty() {
-			Err(JobError::StepCompletedWithErrors(
-				errors.into_iter().map(|e| format!("{e}")).collect(),
-			))
+			Err(JobError::StepCompletedWithErrors(errors))
 		} else {
 			Ok(())
 		}
@@ -203,6 +210,8 @@ impl StatefulJob for IndexerJob {
 
 				data.indexed_count += count as u64;
 				data.db_write_time += start_time.elapsed();
+
+				data.emit_report(&ctx, &state.init.location, &[]);
 			}
 			IndexerJobStepInput::Walk(to_walk_entry) => {
 				let location_id = state.init.location.id;
@@ -264,10 +273,15 @@ impl StatefulJob for IndexerJob {
 					))],
 				);
 
+				let errors = errors
+					.into_iter()
+					.map(|e| format!("{e}"))
+					.collect::<Vec<_>>();
+
+				data.emit_report(&ctx, &state.init.location, &errors);
+
 				if !errors.is_empty() {
-					return Err(JobError::StepCompletedWithErrors(
-						errors.into_iter().map(|e| format!("{e}")).collect(),
-					));
+					return Err(JobError::StepCompletedWithErrors(errors));
 				}
 			}
 		}
@@ -1,4 +1,5 @@
 use crate::{
+	api::CoreEvent,
 	invalidate_query,
 	job::{JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
 	library::Library,
@@ -16,8 +17,11 @@ use std::{
 use rspc::ErrorCode;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
+use serde_with::{serde_as, DisplayFromStr, DurationMilliSeconds};
+use specta::Type;
 use thiserror::Error;
 use tracing::info;
+use uuid::Uuid;
 
 use super::{
 	file_path_helper::{file_path_just_pub_id, FilePathError, IsolatedFilePathData},
@@ -67,6 +71,34 @@ pub struct IndexerJobData {
 }
 
 impl IndexerJobData {
+	/// Sends an [`IndexingJobReport`] of the job so far to the frontend, along with the `errors` of the current step
+	fn emit_report(
+		&self,
+		ctx: &WorkerContext,
+		location: &location_with_indexer_rules::Data,
+		errors: &[String],
+	) {
+		let Ok(location_id) = Uuid::from_slice(&location.pub_id) else {
+			return;
+		};
+
+		ctx.library.emit(CoreEvent::IndexingReport {
+			library_id: ctx.library.id,
+			report: IndexingJobReport {
+				location_id,
+				files_discovered: self.total_paths,
+				files_indexed: self.indexed_count,
+				errors: errors
+					.iter()
+					.map(|message| IndexingError {
+						message: message.clone(),
+					})
+					.collect(),
+				elapsed: self.scan_read_time + self.db_write_time,
+			},
+		});
+	}
+
 	fn on_scan_progress(ctx: &mut WorkerContext, progress: Vec<ScanProgress>) {
 		ctx.progress_debounced(
 			progress
@@ -81,6 +113,30 @@ impl IndexerJobData {
 	}
 }
 
+/// `IndexingJobReport` is emitted as a [`CoreEvent`] while an indexer job runs, so the frontend can show its progress.
+#[serde_as]
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct IndexingJobReport {
+	pub location_id: Uuid,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub files_discovered: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub files_indexed: u64,
+	/// The errors of the step that emitted this report
+	pub errors: Vec<IndexingError>,
+	/// The time spent scanning the location and writing to the database, in milliseconds when serialized
+	#[specta(type = String)]
+	#[serde_as(as = "DurationMilliSeconds<String>")]
+	pub elapsed: Duration,
+}
+
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct IndexingError {
+	pub message: String,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct IndexerJobSaveStep {
 	chunk_idx: usize,
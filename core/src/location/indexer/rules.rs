@@ -1,5 +1,6 @@
 use crate::{
 	library::Library,
+	location::file_path_helper::MetadataExt,
 	prisma::indexer_rule,
 	util::{
 		db::uuid_to_bytes,
@@ -10,13 +11,16 @@ use crate::{
 use chrono::{DateTime, Utc};
 use futures::future::try_join_all;
 use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
 use rmp_serde::{self, decode, encode};
 use rspc::ErrorCode;
 use serde::{de, ser, Deserialize, Serialize};
 use specta::Type;
 use std::{
 	collections::{HashMap, HashSet},
+	fs::Metadata,
 	marker::PhantomData,
+	num::ParseIntError,
 	path::Path,
 };
 use thiserror::Error;
@@ -33,6 +37,14 @@ pub enum IndexerRuleError {
 	Glob(#[from] globset::Error),
 	#[error(transparent)]
 	NonUtf8Path(#[from] NonUtf8PathError),
+	#[error("indexer rule kind {0:?} takes exactly one parameter, but {1} were given")]
+	InvalidParametersCount(RuleKind, usize),
+	#[error("invalid size in bytes")]
+	InvalidSize(#[from] ParseIntError),
+	#[error("regex builder error")]
+	Regex(#[from] regex::Error),
+	#[error("invalid RFC 3339 date")]
+	InvalidDate(#[from] chrono::ParseError),
 
 	// Internal Errors
 	#[error("indexer rule parameters encode error")]
@@ -52,7 +64,11 @@ impl From<IndexerRuleError> for rspc::Error {
 		match err {
 			IndexerRuleError::InvalidRuleKindInt(_)
 			| IndexerRuleError::Glob(_)
-			| IndexerRuleError::NonUtf8Path(_) => {
+			| IndexerRuleError::NonUtf8Path(_)
+			| IndexerRuleError::InvalidParametersCount(_, _)
+			| IndexerRuleError::InvalidSize(_)
+			| IndexerRuleError::Regex(_)
+			| IndexerRuleError::InvalidDate(_) => {
 				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
 			}
 
@@ -69,6 +85,10 @@ impl From<IndexerRuleError> for rspc::Error {
 ///
 /// In case of `RuleKind::AcceptIfChildrenDirectoriesArePresent` or `RuleKind::RejectIfChildrenDirectoriesArePresent` the
 /// `parameters` field must be a vector of strings containing the names of the directories.
+///
+/// The remaining kinds take exactly one parameter: a size in bytes for `RuleKind::MinSize` and
+/// `RuleKind::MaxSize`, a MIME type such as `image/png` or `image/*` for `RuleKind::MimeType`, a regex
+/// for `RuleKind::NameRegex` and a RFC 3339 date for `RuleKind::CreatedBefore`.
 #[derive(Type, Deserialize)]
 pub struct IndexerRuleCreateArgs {
 	pub name: String,
@@ -113,6 +133,25 @@ impl IndexerRuleCreateArgs {
 							parameters.into_iter().collect(),
 						))
 					}
+					RuleKind::MinSize => single_parameter(kind, parameters)
+						.and_then(|size| size.parse::<u64>().map_err(Into::into))
+						.map(RulePerKind::MinSize),
+					RuleKind::MaxSize => single_parameter(kind, parameters)
+						.and_then(|size| size.parse::<u64>().map_err(Into::into))
+						.map(RulePerKind::MaxSize),
+					RuleKind::MimeType => {
+						single_parameter(kind, parameters).map(RulePerKind::MimeType)
+					}
+					RuleKind::NameRegex => single_parameter(kind, parameters)
+						.and_then(|regex| Regex::new(&regex).map_err(Into::into))
+						.map(RulePerKind::NameRegex),
+					RuleKind::CreatedBefore => single_parameter(kind, parameters)
+						.and_then(|date| {
+							DateTime::parse_from_rfc3339(&date)
+								.map(|date| date.with_timezone(&Utc))
+								.map_err(Into::into)
+						})
+						.map(RulePerKind::CreatedBefore),
 				})
 				.collect::<Result<Vec<_>, _>>()?,
 		)?;
@@ -138,6 +177,16 @@ impl IndexerRuleCreateArgs {
 	}
 }
 
+fn single_parameter(kind: RuleKind, parameters: Vec<String>) -> Result<String, IndexerRuleError> {
+	let count = parameters.len();
+	let mut parameters = parameters.into_iter();
+
+	match (parameters.next(), count) {
+		(Some(parameter), 1) => Ok(parameter),
+		_ => Err(IndexerRuleError::InvalidParametersCount(kind, count)),
+	}
+}
+
 #[repr(i32)]
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, Hash)]
@@ -146,12 +195,17 @@ pub enum RuleKind {
 	RejectFilesByGlob = 1,
 	AcceptIfChildrenDirectoriesArePresent = 2,
 	RejectIfChildrenDirectoriesArePresent = 3,
+	MinSize = 4,
+	MaxSize = 5,
+	MimeType = 6,
+	NameRegex = 7,
+	CreatedBefore = 8,
 }
 
 impl RuleKind {
 	pub const fn variant_count() -> usize {
 		// TODO: Use https://doc.rust-lang.org/std/mem/fn.variant_count.html if it ever gets stabilized
-		4
+		9
 	}
 }
 
@@ -162,6 +216,10 @@ impl RuleKind {
 ///
 /// In case of `ParametersPerKind::AcceptIfChildrenDirectoriesArePresent` or `ParametersPerKind::RejectIfChildrenDirectoriesArePresent`
 /// first we change the data structure to a vector, then we serialize it.
+///
+/// `MinSize`, `MaxSize`, `MimeType`, `NameRegex` and `CreatedBefore` are checked against the file's
+/// metadata, and a file is only indexed if it passes all of them. Directories always pass these rules,
+/// so their contents are still walked.
 #[derive(Debug)]
 pub enum RulePerKind {
 	// TODO: Add an indexer rule that filter files based on their extended attributes
@@ -171,6 +229,11 @@ pub enum RulePerKind {
 	RejectFilesByGlob(Vec<Glob>, GlobSet),
 	AcceptIfChildrenDirectoriesArePresent(HashSet<String>),
 	RejectIfChildrenDirectoriesArePresent(HashSet<String>),
+	MinSize(u64),
+	MaxSize(u64),
+	MimeType(String),
+	NameRegex(Regex),
+	CreatedBefore(DateTime<Utc>),
 }
 
 impl RulePerKind {
@@ -210,7 +273,7 @@ impl RulePerKind {
 }
 
 /// We're implementing `Serialize` by hand as `GlobSet`s aren't serializable, so we ignore them on
-/// serialization
+/// serialization. `Regex`es aren't serializable either, so we store their source string instead
 impl Serialize for RulePerKind {
 	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
 	where
@@ -235,6 +298,24 @@ impl Serialize for RulePerKind {
 					"RejectIfChildrenDirectoriesArePresent",
 					children,
 				),
+			RulePerKind::MinSize(ref size) => {
+				serializer.serialize_newtype_variant("ParametersPerKind", 4, "MinSize", size)
+			}
+			RulePerKind::MaxSize(ref size) => {
+				serializer.serialize_newtype_variant("ParametersPerKind", 5, "MaxSize", size)
+			}
+			RulePerKind::MimeType(ref mime_type) => {
+				serializer.serialize_newtype_variant("ParametersPerKind", 6, "MimeType", mime_type)
+			}
+			RulePerKind::NameRegex(ref regex) => serializer.serialize_newtype_variant(
+				"ParametersPerKind",
+				7,
+				"NameRegex",
+				regex.as_str(),
+			),
+			RulePerKind::CreatedBefore(ref date) => {
+				serializer.serialize_newtype_variant("ParametersPerKind", 8, "CreatedBefore", date)
+			}
 		}
 	}
 }
@@ -249,6 +330,11 @@ impl<'de> Deserialize<'de> for RulePerKind {
 			"RejectFilesByGlob",
 			"AcceptIfChildrenDirectoriesArePresent",
 			"RejectIfChildrenDirectoriesArePresent",
+			"MinSize",
+			"MaxSize",
+			"MimeType",
+			"NameRegex",
+			"CreatedBefore",
 		];
 
 		enum Fields {
@@ -256,6 +342,11 @@ impl<'de> Deserialize<'de> for RulePerKind {
 			RejectFilesByGlob,
 			AcceptIfChildrenDirectoriesArePresent,
 			RejectIfChildrenDirectoriesArePresent,
+			MinSize,
+			MaxSize,
+			MimeType,
+			NameRegex,
+			CreatedBefore,
 		}
 
 		struct FieldsVisitor;
@@ -268,7 +359,12 @@ impl<'de> Deserialize<'de> for RulePerKind {
 					"`AcceptFilesByGlob` \
 				or `RejectFilesByGlob` \
 				or `AcceptIfChildrenDirectoriesArePresent` \
-				or `RejectIfChildrenDirectoriesArePresent`",
+				or `RejectIfChildrenDirectoriesArePresent` \
+				or `MinSize` \
+				or `MaxSize` \
+				or `MimeType` \
+				or `NameRegex` \
+				or `CreatedBefore`",
 				)
 			}
 
@@ -281,9 +377,14 @@ impl<'de> Deserialize<'de> for RulePerKind {
 					1 => Ok(Fields::RejectFilesByGlob),
 					2 => Ok(Fields::AcceptIfChildrenDirectoriesArePresent),
 					3 => Ok(Fields::RejectIfChildrenDirectoriesArePresent),
+					4 => Ok(Fields::MinSize),
+					5 => Ok(Fields::MaxSize),
+					6 => Ok(Fields::MimeType),
+					7 => Ok(Fields::NameRegex),
+					8 => Ok(Fields::CreatedBefore),
 					_ => Err(de::Error::invalid_value(
 						de::Unexpected::Unsigned(value),
-						&"variant index 0 <= i < 3",
+						&"variant index 0 <= i < 9",
 					)),
 				}
 			}
@@ -300,6 +401,11 @@ impl<'de> Deserialize<'de> for RulePerKind {
 					"RejectIfChildrenDirectoriesArePresent" => {
 						Ok(Fields::RejectIfChildrenDirectoriesArePresent)
 					}
+					"MinSize" => Ok(Fields::MinSize),
+					"MaxSize" => Ok(Fields::MaxSize),
+					"MimeType" => Ok(Fields::MimeType),
+					"NameRegex" => Ok(Fields::NameRegex),
+					"CreatedBefore" => Ok(Fields::CreatedBefore),
 					_ => Err(de::Error::unknown_variant(value, VARIANTS)),
 				}
 			}
@@ -316,6 +422,11 @@ impl<'de> Deserialize<'de> for RulePerKind {
 					b"RejectIfChildrenDirectoriesArePresent" => {
 						Ok(Fields::RejectIfChildrenDirectoriesArePresent)
 					}
+					b"MinSize" => Ok(Fields::MinSize),
+					b"MaxSize" => Ok(Fields::MaxSize),
+					b"MimeType" => Ok(Fields::MimeType),
+					b"NameRegex" => Ok(Fields::NameRegex),
+					b"CreatedBefore" => Ok(Fields::CreatedBefore),
 					_ => Err(de::Error::unknown_variant(
 						&String::from_utf8_lossy(bytes),
 						VARIANTS,
@@ -401,6 +512,29 @@ impl<'de> Deserialize<'de> for RulePerKind {
 						reject_if_children_directories_are_present,
 					)
 					.map(Self::Value::RejectIfChildrenDirectoriesArePresent),
+					(Fields::MinSize, min_size) => {
+						de::VariantAccess::newtype_variant::<u64>(min_size)
+							.map(Self::Value::MinSize)
+					}
+					(Fields::MaxSize, max_size) => {
+						de::VariantAccess::newtype_variant::<u64>(max_size)
+							.map(Self::Value::MaxSize)
+					}
+					(Fields::MimeType, mime_type) => {
+						de::VariantAccess::newtype_variant::<String>(mime_type)
+							.map(Self::Value::MimeType)
+					}
+					(Fields::NameRegex, name_regex) => {
+						de::VariantAccess::newtype_variant::<String>(name_regex).and_then(|regex| {
+							Regex::new(&regex)
+								.map(Self::Value::NameRegex)
+								.map_err(PPK::Error::custom)
+						})
+					}
+					(Fields::CreatedBefore, created_before) => {
+						de::VariantAccess::newtype_variant::<DateTime<Utc>>(created_before)
+							.map(Self::Value::CreatedBefore)
+					}
 				})
 			}
 		}
@@ -417,7 +551,11 @@ impl<'de> Deserialize<'de> for RulePerKind {
 }
 
 impl RulePerKind {
-	async fn apply(&self, source: impl AsRef<Path>) -> Result<(RuleKind, bool), IndexerRuleError> {
+	async fn apply(
+		&self,
+		source: impl AsRef<Path>,
+		metadata: &Metadata,
+	) -> Result<(RuleKind, bool), IndexerRuleError> {
 		match self {
 			RulePerKind::AcceptIfChildrenDirectoriesArePresent(children) => {
 				accept_dir_for_its_children(source, children)
@@ -438,6 +576,27 @@ impl RulePerKind {
 				RuleKind::RejectFilesByGlob,
 				reject_by_glob(source, reject_glob_set),
 			)),
+
+			RulePerKind::MinSize(min_size) => Ok((
+				RuleKind::MinSize,
+				metadata.is_dir() || metadata.len() >= *min_size,
+			)),
+			RulePerKind::MaxSize(max_size) => Ok((
+				RuleKind::MaxSize,
+				metadata.is_dir() || metadata.len() <= *max_size,
+			)),
+			RulePerKind::MimeType(mime_type) => Ok((
+				RuleKind::MimeType,
+				metadata.is_dir() || accept_by_mime_type(source, mime_type),
+			)),
+			RulePerKind::NameRegex(regex) => Ok((
+				RuleKind::NameRegex,
+				metadata.is_dir() || accept_by_name_regex(source, regex),
+			)),
+			RulePerKind::CreatedBefore(date) => Ok((
+				RuleKind::CreatedBefore,
+				metadata.is_dir() || DateTime::<Utc>::from(metadata.created_or_now()) < *date,
+			)),
 		}
 	}
 }
@@ -467,25 +626,36 @@ impl IndexerRule {
 	pub async fn apply(
 		&self,
 		source: impl AsRef<Path>,
+		metadata: &Metadata,
 	) -> Result<Vec<(RuleKind, bool)>, IndexerRuleError> {
-		try_join_all(self.rules.iter().map(|rule| rule.apply(source.as_ref()))).await
+		try_join_all(
+			self.rules
+				.iter()
+				.map(|rule| rule.apply(source.as_ref(), metadata)),
+		)
+		.await
 	}
 
 	pub async fn apply_all(
 		rules: &[IndexerRule],
 		source: impl AsRef<Path>,
+		metadata: &Metadata,
 	) -> Result<HashMap<RuleKind, Vec<bool>>, IndexerRuleError> {
-		try_join_all(rules.iter().map(|rule| rule.apply(source.as_ref())))
-			.await
-			.map(|results| {
-				results.into_iter().flatten().fold(
-					HashMap::with_capacity(RuleKind::variant_count()),
-					|mut map, (kind, result)| {
-						map.entry(kind).or_insert_with(Vec::new).push(result);
-						map
-					},
-				)
-			})
+		try_join_all(
+			rules
+				.iter()
+				.map(|rule| rule.apply(source.as_ref(), metadata)),
+		)
+		.await
+		.map(|results| {
+			results.into_iter().flatten().fold(
+				HashMap::with_capacity(RuleKind::variant_count()),
+				|mut map, (kind, result)| {
+					map.entry(kind).or_insert_with(Vec::new).push(result);
+					map
+				},
+			)
+		})
 	}
 }
 
@@ -520,6 +690,24 @@ fn reject_by_glob(source: impl AsRef<Path>, reject_glob_set: &GlobSet) -> bool {
 	!accept_by_glob(source.as_ref(), reject_glob_set)
 }
 
+/// `mime_type` can also be a whole top level type, like `image/*`
+fn accept_by_mime_type(source: impl AsRef<Path>, mime_type: &str) -> bool {
+	mime_guess::from_path(source).iter().any(|guess| {
+		mime_type == guess.essence_str()
+			|| mime_type
+				.strip_suffix("/*")
+				.map_or(false, |type_| type_ == guess.type_().as_str())
+	})
+}
+
+fn accept_by_name_regex(source: impl AsRef<Path>, regex: &Regex) -> bool {
+	source
+		.as_ref()
+		.file_name()
+		.and_then(|name| name.to_str())
+		.map_or(false, |name| regex.is_match(name))
+}
+
 async fn accept_dir_for_its_children(
 	source: impl AsRef<Path>,
 	children: &HashSet<String>,
@@ -633,9 +821,9 @@ mod seeder {
 		DatabaseError(#[from] prisma_client_rust::QueryError),
 	}
 
-	struct SystemIndexerRule {
+	pub(super) struct SystemIndexerRule {
 		name: &'static str,
-		rules: Vec<RulePerKind>,
+		pub(super) rules: Vec<RulePerKind>,
 	}
 
 	pub async fn seeder(client: &PrismaClient) -> Result<(), SeederError> {
@@ -645,6 +833,7 @@ mod seeder {
 			no_hidden(),
 			only_git_repos(),
 			only_images(),
+			no_build_artifacts(),
 		]
 		.into_iter()
 		.enumerate()
@@ -801,6 +990,20 @@ mod seeder {
 			.unwrap()],
 		}
 	}
+
+	/// Only what's inside these directories is rejected, as a file with the same name (e.g. a `build` script) can't hold
+	/// anything. The directories themselves are still indexed, just without their contents.
+	pub(super) fn no_build_artifacts() -> SystemIndexerRule {
+		const DIRS: &str =
+			"{node_modules,.git,target,build,dist,out,__pycache__,.venv,.gradle,.next,.turbo}";
+
+		SystemIndexerRule {
+			name: "No Build Artifacts",
+			rules: vec![
+				RulePerKind::new_reject_files_by_globs_str([format!("**/{DIRS}/*")]).unwrap(),
+			],
+		}
+	}
 }
 
 pub use seeder::*;
@@ -812,8 +1015,19 @@ mod tests {
 	use tokio::fs;
 
 	async fn check_rule(indexer_rule: &IndexerRule, path: impl AsRef<Path>) -> bool {
+		// Rules based on paths don't look at the metadata, so any metadata will do
+		let metadata = fs::metadata(env!("CARGO_MANIFEST_DIR")).await.unwrap();
+
+		check_rule_with_metadata(indexer_rule, path, &metadata).await
+	}
+
+	async fn check_rule_with_metadata(
+		indexer_rule: &IndexerRule,
+		path: impl AsRef<Path>,
+		metadata: &Metadata,
+	) -> bool {
 		indexer_rule
-			.apply(path)
+			.apply(path, metadata)
 			.await
 			.unwrap()
 			.into_iter()
@@ -871,6 +1085,31 @@ mod tests {
 		assert!(!check_rule(&rule, project_build_dir_inner).await);
 	}
 
+	#[tokio::test]
+	async fn test_no_build_artifacts() {
+		let rule = IndexerRule::new(
+			"No Build Artifacts".to_string(),
+			false,
+			seeder::no_build_artifacts().rules,
+		);
+
+		assert!(check_rule(&rule, Path::new("/test/project/src/main.rs")).await);
+		assert!(check_rule(&rule, Path::new("/test/project/builder.rs")).await);
+		// a file named like a build directory can't have build artifacts in it
+		assert!(check_rule(&rule, Path::new("/test/project/build")).await);
+		assert!(check_rule(&rule, Path::new("/test/project/scripts/out")).await);
+		assert!(!check_rule(&rule, Path::new("/test/project/build/app.js")).await);
+		assert!(
+			!check_rule(
+				&rule,
+				Path::new("/test/project/node_modules/react/index.js")
+			)
+			.await
+		);
+		assert!(!check_rule(&rule, Path::new("/test/project/.git/HEAD")).await);
+		assert!(!check_rule(&rule, Path::new("/test/project/target/debug/")).await);
+	}
+
 	#[tokio::test]
 	async fn test_only_photos() {
 		let text = Path::new("file.txt");
@@ -969,6 +1208,130 @@ mod tests {
 		assert!(check_rule(&rule, not_project).await);
 	}
 
+	async fn check_file_rule(indexer_rule: &IndexerRule, path: impl AsRef<Path>) -> bool {
+		let path = path.as_ref();
+		let metadata = fs::metadata(path).await.unwrap();
+
+		check_rule_with_metadata(indexer_rule, path, &metadata).await
+	}
+
+	#[tokio::test]
+	async fn test_file_size() {
+		let root = tempdir().unwrap();
+
+		let small = root.path().join("small.txt");
+		let big = root.path().join("big.txt");
+		let dir = root.path().join("dir");
+
+		fs::write(&small, [0; 10]).await.unwrap();
+		fs::write(&big, [0; 1000]).await.unwrap();
+		fs::create_dir(&dir).await.unwrap();
+
+		let min_size = IndexerRule::new(
+			"at least 100 bytes".to_string(),
+			false,
+			vec![RulePerKind::MinSize(100)],
+		);
+		let max_size = IndexerRule::new(
+			"at most 100 bytes".to_string(),
+			false,
+			vec![RulePerKind::MaxSize(100)],
+		);
+
+		assert!(!check_file_rule(&min_size, &small).await);
+		assert!(check_file_rule(&min_size, &big).await);
+		assert!(check_file_rule(&min_size, &dir).await);
+		assert!(check_file_rule(&max_size, &small).await);
+		assert!(!check_file_rule(&max_size, &big).await);
+		assert!(check_file_rule(&max_size, &dir).await);
+	}
+
+	#[tokio::test]
+	async fn test_mime_type() {
+		let root = tempdir().unwrap();
+
+		let png = root.path().join("photo.png");
+		let jpg = root.path().join("photo.jpg");
+		let text = root.path().join("notes.txt");
+		let dir = root.path().join("photos");
+
+		fs::write(&png, []).await.unwrap();
+		fs::write(&jpg, []).await.unwrap();
+		fs::write(&text, []).await.unwrap();
+		fs::create_dir(&dir).await.unwrap();
+
+		let only_png = IndexerRule::new(
+			"only png".to_string(),
+			false,
+			vec![RulePerKind::MimeType("image/png".to_string())],
+		);
+		let only_images = IndexerRule::new(
+			"only images".to_string(),
+			false,
+			vec![RulePerKind::MimeType("image/*".to_string())],
+		);
+
+		assert!(check_file_rule(&only_png, &png).await);
+		assert!(!check_file_rule(&only_png, &jpg).await);
+		assert!(!check_file_rule(&only_png, &text).await);
+		assert!(check_file_rule(&only_png, &dir).await);
+		assert!(check_file_rule(&only_images, &png).await);
+		assert!(check_file_rule(&only_images, &jpg).await);
+		assert!(!check_file_rule(&only_images, &text).await);
+	}
+
+	#[tokio::test]
+	async fn test_name_regex() {
+		let root = tempdir().unwrap();
+
+		let camera = root.path().join("IMG_0042.jpg");
+		let edited = root.path().join("IMG_0042 (edited).jpg");
+		let dir = root.path().join("DCIM");
+
+		fs::write(&camera, []).await.unwrap();
+		fs::write(&edited, []).await.unwrap();
+		fs::create_dir(&dir).await.unwrap();
+
+		let rule = IndexerRule::new(
+			"camera photos".to_string(),
+			false,
+			vec![RulePerKind::NameRegex(
+				Regex::new(r"^IMG_\d+\.jpg$").unwrap(),
+			)],
+		);
+
+		assert!(check_file_rule(&rule, &camera).await);
+		assert!(!check_file_rule(&rule, &edited).await);
+		assert!(check_file_rule(&rule, &dir).await);
+	}
+
+	#[tokio::test]
+	async fn test_created_before() {
+		let root = tempdir().unwrap();
+
+		let file = root.path().join("file.txt");
+		fs::write(&file, []).await.unwrap();
+
+		let tomorrow = IndexerRule::new(
+			"created before tomorrow".to_string(),
+			false,
+			vec![RulePerKind::CreatedBefore(
+				Utc::now() + chrono::Duration::days(1),
+			)],
+		);
+		let yesterday = IndexerRule::new(
+			"created before yesterday".to_string(),
+			false,
+			vec![RulePerKind::CreatedBefore(
+				Utc::now() - chrono::Duration::days(1),
+			)],
+		);
+
+		assert!(check_file_rule(&tomorrow, &file).await);
+		assert!(!check_file_rule(&yesterday, &file).await);
+		assert!(check_file_rule(&yesterday, root.path()).await);
+	}
+
 	impl PartialEq for RulePerKind {
 		fn eq(&self, other: &Self) -> bool {
 			match (self, other) {
@@ -988,6 +1351,21 @@ mod tests {
 					RulePerKind::RejectIfChildrenDirectoriesArePresent(self_childrens),
 					RulePerKind::RejectIfChildrenDirectoriesArePresent(other_childrens),
 				) => self_childrens == other_childrens,
+				(RulePerKind::MinSize(self_size), RulePerKind::MinSize(other_size)) => {
+					self_size == other_size
+				}
+				(RulePerKind::MaxSize(self_size), RulePerKind::MaxSize(other_size)) => {
+					self_size == other_size
+				}
+				(RulePerKind::MimeType(self_mime), RulePerKind::MimeType(other_mime)) => {
+					self_mime == other_mime
+				}
+				(RulePerKind::NameRegex(self_regex), RulePerKind::NameRegex(other_regex)) => {
+					self_regex.as_str() == other_regex.as_str()
+				}
+				(RulePerKind::CreatedBefore(self_date), RulePerKind::CreatedBefore(other_date)) => {
+					self_date == other_date
+				}
 				_ => false,
 			}
 		}
@@ -1027,4 +1405,25 @@ mod tests {
 
 		assert_eq!(actual, expected);
 	}
+
+	#[test]
+	fn serde_metadata_rules_smoke_test() {
+		let actual = IndexerRule::new(
+			"Small Camera Photos".to_string(),
+			false,
+			vec![
+				RulePerKind::MinSize(1024),
+				RulePerKind::MaxSize(10 * 1024 * 1024),
+				RulePerKind::MimeType("image/*".to_string()),
+				RulePerKind::NameRegex(Regex::new(r"^IMG_\d+").unwrap()),
+				RulePerKind::CreatedBefore(Utc::now()),
+			],
+		);
+
+		let expected =
+			rmp_serde::from_slice::<IndexerRule>(&rmp_serde::to_vec_named(&actual).unwrap())
+				.unwrap();
+
+		assert_eq!(actual, expected);
+	}
 }
@@ -404,7 +404,15 @@ where
 			accept_by_children_dir
 		);
 
-		let Ok(rules_per_kind) = IndexerRule::apply_all(indexer_rules, &current_path).await
+		let Ok(metadata) = entry
+			.metadata()
+			.await
+			.map_err(|e| errors.push(FileIOError::from((entry.path(), e)).into()))
+			else {
+				continue 'entries;
+		};
+
+		let Ok(rules_per_kind) = IndexerRule::apply_all(indexer_rules, &current_path, &metadata).await
 			.map_err(|e| errors.push(e.into()))
 			else {
 			continue 'entries;
@@ -422,14 +430,6 @@ where
 			continue 'entries;
 		}
 
-		let Ok(metadata) = entry
-			.metadata()
-			.await
-			.map_err(|e| errors.push(FileIOError::from((entry.path(), e)).into()))
-			else {
-				continue 'entries;
-		};
-
 		// TODO: Hard ignoring symlinks for now, but this should be configurable
 		if metadata.is_symlink() {
 			continue 'entries;
@@ -505,6 +505,25 @@ where
 			continue 'entries;
 		}
 
+		for kind in [
+			RuleKind::MinSize,
+			RuleKind::MaxSize,
+			RuleKind::MimeType,
+			RuleKind::NameRegex,
+			RuleKind::CreatedBefore,
+		] {
+			if rules_per_kind
+				.get(&kind)
+				.map_or(false, |results| results.iter().any(|passed| !passed))
+			{
+				trace!(
+					"Path {} rejected by `RuleKind::{kind:?}`",
+					current_path.display()
+				);
+				continue 'entries;
+			}
+		}
+
 		if accept_by_children_dir.is_none() || accept_by_children_dir.expect("<-- checked") {
 			let Ok(iso_file_path) = iso_file_path_factory(&current_path, is_dir)
 				.map_err(|e| errors.push(e))
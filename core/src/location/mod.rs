@@ -31,11 +31,13 @@ pub mod file_path_helper;
 pub mod indexer;
 mod manager;
 mod metadata;
+mod move_object;
 
 pub use error::LocationError;
 use indexer::IndexerJobInit;
 pub use manager::{LocationManager, LocationManagerError};
 use metadata::SpacedriveLocationMetadataFile;
+pub use move_object::move_object;
 
 pub type LocationId = i32;
 
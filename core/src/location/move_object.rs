@@ -0,0 +1,310 @@
+use crate::{
+	library::LibraryManagerError,
+	prisma::{file_path, location, object, PrismaClient},
+	util::error::FileIOError,
+};
+
+use std::path::{Component, Path};
+
+use tokio::{fs, io};
+use tracing::error;
+use uuid::Uuid;
+
+use super::file_path_helper::{FilePathError, IsolatedFilePathData};
+
+/// This moves the file of an object to `target_relative_path` inside of another location, keeping the object's row.
+///
+/// The object's `file_path` is updated in place rather than being recreated, so its tags, notes and other relations survive the move.
+/// If the database can't be updated, the file is moved back. Objects that have more than one file path can't be moved,
+/// as it would be ambiguous which file was meant, and neither can directories, as the rows of everything inside of them would
+/// have to be moved too. Missing parent directories of the target are created.
+///
+/// The location watchers will see the file disappear from one location and appear in the other,
+/// but as the `file_path` is already in place by then they won't create a new object for it.
+pub async fn move_object(
+	db: &PrismaClient,
+	object_id: Uuid,
+	target_location_id: Uuid,
+	target_relative_path: &Path,
+) -> Result<(), LibraryManagerError> {
+	if !target_relative_path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_)))
+	{
+		return Err(LibraryManagerError::InvalidPath(
+			target_relative_path.to_path_buf(),
+		));
+	}
+
+	let (file_paths, target_location) = tokio::try_join!(
+		db.file_path()
+			.find_many(vec![file_path::object::is(vec![object::pub_id::equals(
+				object_id.as_bytes().to_vec()
+			)])])
+			.exec(),
+		db.location()
+			.find_unique(location::pub_id::equals(
+				target_location_id.as_bytes().to_vec()
+			))
+			.exec(),
+	)?;
+
+	let file_path = match file_paths.as_slice() {
+		[] => return Err(LibraryManagerError::ObjectNotFound(object_id)),
+		[file_path] => file_path,
+		_ => return Err(LibraryManagerError::AmbiguousObjectMove(object_id)),
+	};
+	if file_path.is_dir {
+		return Err(LibraryManagerError::DirectoryObjectMove(object_id));
+	}
+	let target_location =
+		target_location.ok_or(LibraryManagerError::LocationNotFound(target_location_id))?;
+	let source_location = db
+		.location()
+		.find_unique(location::id::equals(file_path.location_id))
+		.select(location::select!({ path }))
+		.exec()
+		.await?
+		.ok_or(FilePathError::LocationNotFound(file_path.location_id))?;
+
+	let source = Path::new(&source_location.path).join(IsolatedFilePathData::from(file_path));
+	let target = Path::new(&target_location.path).join(target_relative_path);
+	let target_iso_file_path = IsolatedFilePathData::new(
+		target_location.id,
+		&target_location.path,
+		&target,
+		file_path.is_dir,
+	)?;
+
+	match fs::metadata(&target).await {
+		Ok(_) => return Err(LibraryManagerError::FileAlreadyExists(target)),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+		Err(e) => return Err(FileIOError::from((target, e)).into()),
+	}
+
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent)
+			.await
+			.map_err(|e| FileIOError::from((parent, e)))?;
+	}
+
+	move_file(&source, &target).await?;
+
+	let res = db
+		._transaction()
+		.run(|tx| {
+			let (target, target_iso_file_path) = (&target, &target_iso_file_path);
+
+			async move {
+				// a stale row for the target would otherwise fail the update on the unique constraint
+				if tx
+					.file_path()
+					.find_unique(target_iso_file_path.into())
+					.exec()
+					.await?
+					.is_some()
+				{
+					return Err(LibraryManagerError::FileAlreadyExists(target.clone()));
+				}
+
+				tx.file_path()
+					.update(
+						file_path::pub_id::equals(file_path.pub_id.clone()),
+						vec![
+							file_path::location::connect(location::id::equals(target_location.id)),
+							file_path::materialized_path::set(
+								target_iso_file_path.materialized_path.to_string(),
+							),
+							file_path::name::set(target_iso_file_path.name.to_string()),
+							file_path::extension::set(target_iso_file_path.extension.to_string()),
+						],
+					)
+					.exec()
+					.await?;
+
+				Ok(())
+			}
+		})
+		.await;
+
+	if res.is_err() {
+		if let Err(e) = move_file(&target, &source).await {
+			error!(
+				"Failed to move '{}' back to '{}' after the database couldn't be updated: {e:#?}",
+				target.display(),
+				source.display()
+			);
+		}
+	}
+
+	res
+}
+
+/// Renames `from` to `to`, falling back to copying and removing it when they're on different volumes (which can't be renamed across)
+async fn move_file(from: &Path, to: &Path) -> Result<(), FileIOError> {
+	let Err(rename_err) = fs::rename(from, to).await else {
+		return Ok(());
+	};
+
+	if fs::copy(from, to).await.is_err() {
+		return Err(FileIOError::from((from, rename_err)));
+	}
+
+	fs::remove_file(from)
+		.await
+		.map_err(|e| FileIOError::from((from, e)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::db::{test_db, uuid_to_bytes};
+
+	use tempfile::TempDir;
+
+	struct TestLibrary {
+		dir: TempDir,
+		db: PrismaClient,
+		source: location::Data,
+		target: location::Data,
+	}
+
+	async fn test_library() -> TestLibrary {
+		let (dir, db) = test_db().await;
+		let node = db
+			.node()
+			.create(uuid_to_bytes(Uuid::new_v4()), "node".to_string(), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		let mut locations = vec![];
+		for name in ["source", "target"] {
+			let path = dir.path().join(name);
+			fs::create_dir(&path).await.unwrap();
+
+			locations.push(
+				db.location()
+					.create_unchecked(
+						uuid_to_bytes(Uuid::new_v4()),
+						node.id,
+						name.to_string(),
+						path.to_str().unwrap().to_string(),
+						vec![],
+					)
+					.exec()
+					.await
+					.unwrap(),
+			);
+		}
+		let target = locations.pop().unwrap();
+		let source = locations.pop().unwrap();
+
+		TestLibrary {
+			dir,
+			db,
+			source,
+			target,
+		}
+	}
+
+	/// This creates an object with one file path at the root of the source location, which is a directory if `is_dir` is set
+	async fn test_object(library: &TestLibrary, name: &str, extension: &str, is_dir: bool) -> Uuid {
+		let pub_id = Uuid::new_v4();
+		let object = library
+			.db
+			.object()
+			.create(uuid_to_bytes(pub_id), vec![])
+			.exec()
+			.await
+			.unwrap();
+
+		library
+			.db
+			.file_path()
+			.create_unchecked(
+				uuid_to_bytes(Uuid::new_v4()),
+				library.source.id,
+				"/".to_string(),
+				name.to_string(),
+				extension.to_string(),
+				vec![1; 8],
+				vec![0; 8],
+				vec![
+					file_path::is_dir::set(is_dir),
+					file_path::object_id::set(Some(object.id)),
+				],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		pub_id
+	}
+
+	#[tokio::test]
+	async fn directories_are_rejected() {
+		let library = test_library().await;
+		let source = library.dir.path().join("source/photos");
+		fs::create_dir(&source).await.unwrap();
+		fs::write(source.join("a.png"), b"a").await.unwrap();
+		let object = test_object(&library, "photos", "", true).await;
+
+		assert!(matches!(
+			move_object(
+				&library.db,
+				object,
+				Uuid::from_slice(&library.target.pub_id).unwrap(),
+				Path::new("photos"),
+			)
+			.await,
+			Err(LibraryManagerError::DirectoryObjectMove(id)) if id == object
+		));
+		assert!(source.join("a.png").exists());
+		assert!(!library.dir.path().join("target/photos").exists());
+	}
+
+	#[tokio::test]
+	async fn missing_parent_directories_are_created() {
+		let library = test_library().await;
+		fs::write(library.dir.path().join("source/notes.txt"), b"notes")
+			.await
+			.unwrap();
+		let object = test_object(&library, "notes", "txt", false).await;
+
+		move_object(
+			&library.db,
+			object,
+			Uuid::from_slice(&library.target.pub_id).unwrap(),
+			Path::new("2023/june/notes.txt"),
+		)
+		.await
+		.unwrap();
+
+		assert!(!library.dir.path().join("source/notes.txt").exists());
+		assert_eq!(
+			fs::read(library.dir.path().join("target/2023/june/notes.txt"))
+				.await
+				.unwrap(),
+			b"notes"
+		);
+
+		let file_path = library
+			.db
+			.file_path()
+			.find_first(vec![file_path::object::is(vec![object::pub_id::equals(
+				uuid_to_bytes(object),
+			)])])
+			.exec()
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(file_path.location_id, library.target.id);
+		assert_eq!(file_path.materialized_path, "/2023/june/");
+		assert_eq!(
+			(file_path.name.as_str(), file_path.extension.as_str()),
+			("notes", "txt")
+		);
+	}
+}
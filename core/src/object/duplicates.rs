@@ -0,0 +1,119 @@
+use crate::{
+	location::{file_path_helper::IsolatedFilePathData, LocationError},
+	prisma::{file_path, location, PrismaClient, SortOrder},
+};
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+
+/// DuplicateGroup is a set of files in a location that have the same content, or most likely do.
+#[serde_as]
+#[derive(Serialize, Type, Debug)]
+pub struct DuplicateGroup {
+	/// The content addressable id that's shared by the files, which only hashes a sample of their content
+	pub cas_id: String,
+	/// The checksum of the full content that's shared by the files, if they've been through the object validator.
+	/// Groups without one are only likely to be duplicates, as files with the same `cas_id` can still differ outside of the sampled bytes.
+	pub integrity_checksum: Option<String>,
+	pub paths: Vec<PathBuf>,
+	/// How much space would be freed by keeping only one of the files
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub total_wasted_bytes: u64,
+}
+
+/// DuplicatesPage is a page of the [`DuplicateGroup`]s returned by [`find_duplicates`].
+#[derive(Serialize, Type, Debug)]
+pub struct DuplicatesPage {
+	pub groups: Vec<DuplicateGroup>,
+	/// How many groups were found in total, across all pages
+	pub total_groups: u32,
+	/// How many files in the location haven't been identified yet, so couldn't be checked for duplicates.
+	/// They can be identified with `jobs.identifyUniqueFiles`.
+	pub unhashed_files: u32,
+}
+
+/// This finds the files in a location that are duplicates of each other, by grouping them by their `cas_id`.
+///
+/// Files that have an `integrity_checksum` are only grouped with files that have the same one, so a group is either fully
+/// verified or made of files that haven't been through the object validator yet.
+///
+/// Groups are ordered by how much space they waste, and the page of `take` groups starting at `skip` is returned.
+/// Files that haven't been through the file identifier yet don't have a `cas_id`, and are only counted in `unhashed_files`.
+pub async fn find_duplicates(
+	db: &PrismaClient,
+	location_id: i32,
+	order: SortOrder,
+	skip: usize,
+	take: usize,
+) -> Result<DuplicatesPage, LocationError> {
+	let location = db
+		.location()
+		.find_unique(location::id::equals(location_id))
+		.select(location::select!({ path }))
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+	let location_path = PathBuf::from(location.path);
+
+	let (file_paths, unhashed_files) = tokio::try_join!(
+		db.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(location_id),
+				file_path::is_dir::equals(false),
+				file_path::cas_id::not(None),
+			])
+			.exec(),
+		db.file_path()
+			.count(vec![
+				file_path::location_id::equals(location_id),
+				file_path::is_dir::equals(false),
+				file_path::cas_id::equals(None),
+			])
+			.exec(),
+	)?;
+
+	let mut by_content = HashMap::<_, Vec<_>>::new();
+	for file_path in file_paths {
+		if let Some(cas_id) = file_path.cas_id.clone() {
+			by_content
+				.entry((cas_id, file_path.integrity_checksum.clone()))
+				.or_default()
+				.push(file_path);
+		}
+	}
+
+	let mut groups = by_content
+		.into_iter()
+		.filter(|(_, file_paths)| file_paths.len() > 1)
+		.map(|((cas_id, integrity_checksum), file_paths)| {
+			let size = file_paths[0].size_in_bytes.parse::<u64>().unwrap_or(0);
+
+			DuplicateGroup {
+				cas_id,
+				integrity_checksum,
+				total_wasted_bytes: size * (file_paths.len() as u64 - 1),
+				paths: file_paths
+					.iter()
+					.map(|file_path| location_path.join(IsolatedFilePathData::from(file_path)))
+					.collect(),
+			}
+		})
+		.collect::<Vec<_>>();
+
+	groups.sort_by(|a, b| match order {
+		SortOrder::Asc => a.total_wasted_bytes.cmp(&b.total_wasted_bytes),
+		SortOrder::Desc => b.total_wasted_bytes.cmp(&a.total_wasted_bytes),
+	});
+
+	let total_groups = groups.len() as u32;
+
+	Ok(DuplicatesPage {
+		groups: groups.into_iter().skip(skip).take(take).collect(),
+		total_groups,
+		unhashed_files: unhashed_files as u32,
+	})
+}
@@ -4,7 +4,7 @@ use crate::{
 	location::file_path_helper::{
 		file_path_for_file_identifier, FilePathError, IsolatedFilePathData,
 	},
-	object::{cas::generate_cas_id, object_for_file_identifier},
+	object::{cas::generate_cas_id, object_for_file_identifier, preview::save_exif_data},
 	prisma::{file_path, location, object, PrismaClient},
 	sync,
 	sync::SyncManager,
@@ -227,12 +227,22 @@ async fn identifier_job_step(
 			new_objects_cas_ids
 		);
 
+		let mut exif_candidates = Vec::new();
+
 		let (object_create_args, file_path_update_args): (Vec<_>, Vec<_>) =
 			file_paths_requiring_new_object
 				.iter()
 				.map(|(file_path_pub_id, (meta, fp))| {
 					let object_pub_id = Uuid::new_v4();
 
+					if meta.kind == ObjectKind::Image {
+						exif_candidates.push((
+							object_pub_id,
+							Path::new(&location.path)
+								.join(IsolatedFilePathData::from((location.id, *fp))),
+						));
+					}
+
 					let sync_id = || sync::object::SyncId {
 						pub_id: uuid_to_bytes(object_pub_id),
 					};
@@ -299,6 +309,12 @@ async fn identifier_job_step(
 			.await?;
 
 			info!("Updated file paths with created objects");
+
+			// exif data is only a nice to have, so failing to save it doesn't fail the step
+			match save_exif_data(db, exif_candidates).await {
+				Ok(count) => info!("Saved exif data of {count} new Objects"),
+				Err(e) => error!("Error saving exif data: {e:#?}"),
+			}
 		}
 
 		total_created_files as usize
@@ -1,5 +1,5 @@
 use crate::{
-	invalidate_query,
+	chain_opt, invalidate_query,
 	job::JobError,
 	library::Library,
 	location::file_path_helper::{
@@ -7,7 +7,6 @@ use crate::{
 		file_path_for_file_identifier, IsolatedFilePathData,
 	},
 	prisma::{file_path, location, PrismaClient, SortOrder},
-	util::db::chain_optional_iter,
 };
 
 use std::path::{Path, PathBuf};
@@ -119,19 +118,17 @@ fn orphan_path_filters(
 	file_path_id: Option<i32>,
 	sub_iso_file_path: &IsolatedFilePathData<'_>,
 ) -> Vec<file_path::WhereParam> {
-	chain_optional_iter(
-		[
-			file_path::object_id::equals(None),
-			file_path::is_dir::equals(false),
-			file_path::location_id::equals(location_id),
-			file_path::materialized_path::equals(
-				sub_iso_file_path
-					.materialized_path_for_children()
-					.expect("sub path for shallow identifier must be a directory"),
-			),
-		],
-		[file_path_id.map(file_path::id::gte)],
-	)
+	chain_opt![
+		file_path::object_id::equals(None),
+		file_path::is_dir::equals(false),
+		file_path::location_id::equals(location_id),
+		file_path::materialized_path::equals(
+			sub_iso_file_path
+				.materialized_path_for_children()
+				.expect("sub path for shallow identifier must be a directory"),
+		),
+		?file_path_id.map(file_path::id::gte),
+	]
 }
 
 async fn count_orphan_file_paths(
@@ -6,12 +6,16 @@ use crate::{
 	util::error::FileIOError,
 };
 
-use std::{hash::Hash, path::PathBuf};
+use std::{
+	hash::Hash,
+	path::{Path, PathBuf},
+};
 
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::fs;
-use tracing::{trace, warn};
+use tracing::{error, trace, warn};
 
 use super::{context_menu_fs_info, get_location_path_from_location_id, FsInfo};
 
@@ -23,6 +27,42 @@ pub struct FileCutterJobInit {
 	pub source_path_id: i32,
 	pub target_location_id: i32,
 	pub target_path: PathBuf,
+	/// What to do when the target directory already has a file with the same name.
+	/// When unset, the move fails instead.
+	pub conflict_resolution: Option<FileConflictResolution>,
+}
+
+/// `FileConflictResolution` decides what happens to files that would overwrite an existing file when they're moved.
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileConflictResolution {
+	/// Leave the file where it is
+	Skip,
+	/// Replace the existing file
+	Overwrite,
+	/// Move the file under a new name
+	Rename(RenameStrategy),
+}
+
+/// `RenameStrategy` decides how a conflicting file is renamed by [`FileConflictResolution::Rename`].
+#[derive(Serialize, Deserialize, Hash, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameStrategy {
+	/// Append the first free ` (1)`, ` (2)`, ... to the file name
+	Incrementing,
+	/// Append the current date and time to the file name
+	Timestamp,
+}
+
+/// `ConflictOutcome` records how a conflicting file was handled.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ConflictOutcome {
+	Skipped { source: PathBuf },
+	Overwritten { target: PathBuf },
+	Renamed { source: PathBuf, target: PathBuf },
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileCutterJobData {
+	pub conflicts: Vec<ConflictOutcome>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -38,7 +78,7 @@ impl JobInitData for FileCutterJobInit {
 #[async_trait::async_trait]
 impl StatefulJob for FileCutterJob {
 	type Init = FileCutterJobInit;
-	type Data = ();
+	type Data = FileCutterJobData;
 	type Step = FileCutterJobStep;
 
 	const NAME: &'static str = "file_cutter";
@@ -65,6 +105,8 @@ impl StatefulJob for FileCutterJob {
 			target_directory: full_target_path,
 		});
 
+		state.data = Some(FileCutterJobData::default());
+
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
 		Ok(())
@@ -78,7 +120,7 @@ impl StatefulJob for FileCutterJob {
 		let step = &state.steps[0];
 		let source_info = &step.source_fs_info;
 
-		let full_output = step
+		let mut full_output = step
 			.target_directory
 			.join(source_info.fs_path.file_name().ok_or(JobError::OsStr)?);
 
@@ -96,13 +138,65 @@ impl StatefulJob for FileCutterJob {
 			return Err(JobError::MatchingSrcDest(source_info.fs_path.clone()));
 		}
 
-		if fs::metadata(&full_output).await.is_ok() {
-			warn!(
-				"Skipping {} as it would be overwritten",
-				full_output.display()
-			);
+		if let Ok(existing) = fs::metadata(&full_output).await {
+			let data = state.data.get_or_insert_with(Default::default);
+
+			match state.init.conflict_resolution {
+				None => {
+					warn!(
+						"Skipping {} as it would be overwritten",
+						full_output.display()
+					);
+
+					return Err(JobError::WouldOverwrite(full_output));
+				}
+				Some(FileConflictResolution::Skip) => {
+					trace!(
+						"Skipping {} as it would be overwritten",
+						full_output.display()
+					);
+
+					data.conflicts.push(ConflictOutcome::Skipped {
+						source: source_info.fs_path.clone(),
+					});
+					ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+						state.step_number + 1,
+					)]);
+
+					return Ok(());
+				}
+				Some(FileConflictResolution::Overwrite) => {
+					if existing.is_dir() != source_info.path_data.is_dir {
+						return Err(JobError::MismatchedOverwrite(full_output));
+					}
 
-			return Err(JobError::WouldOverwrite(full_output));
+					trace!(
+						"Cutting {} over {}",
+						source_info.fs_path.display(),
+						full_output.display()
+					);
+
+					replace(&source_info.fs_path, &full_output).await?;
+
+					data.conflicts.push(ConflictOutcome::Overwritten {
+						target: full_output,
+					});
+					ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+						state.step_number + 1,
+					)]);
+
+					return Ok(());
+				}
+				Some(FileConflictResolution::Rename(strategy)) => {
+					let renamed = find_available_path(&full_output, strategy).await?;
+
+					data.conflicts.push(ConflictOutcome::Renamed {
+						source: source_info.fs_path.clone(),
+						target: renamed.clone(),
+					});
+					full_output = renamed;
+				}
+			}
 		}
 
 		trace!(
@@ -124,6 +218,144 @@ impl StatefulJob for FileCutterJob {
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
 		invalidate_query!(ctx.library, "search.paths");
 
-		Ok(Some(serde_json::to_value(&state.init)?))
+		Ok(Some(serde_json::json!({
+			"init": state.init,
+			"conflicts": state.data.as_ref().map(|data| &data.conflicts),
+		})))
+	}
+}
+
+/// Moves `source` over `target`, which has to be of the same kind (file or directory).
+///
+/// Renaming replaces a file atomically, but a directory can't replace another one, so the existing directory is set aside
+/// until `source` is in place, and put back if it can't be moved.
+async fn replace(source: &Path, target: &Path) -> Result<(), JobError> {
+	if !fs::metadata(target)
+		.await
+		.map_err(|e| FileIOError::from((target, e)))?
+		.is_dir()
+	{
+		return fs::rename(source, target)
+			.await
+			.map_err(|e| FileIOError::from((source, e)).into());
+	}
+
+	let aside = find_available_path(target, RenameStrategy::Timestamp).await?;
+	fs::rename(target, &aside)
+		.await
+		.map_err(|e| FileIOError::from((target, e)))?;
+
+	if let Err(e) = fs::rename(source, target).await {
+		if let Err(restore_err) = fs::rename(&aside, target).await {
+			error!(
+				"Failed to put '{}' back after it couldn't be overwritten: {restore_err:#?}",
+				target.display()
+			);
+		}
+
+		return Err(FileIOError::from((source, e)).into());
+	}
+
+	fs::remove_dir_all(&aside)
+		.await
+		.map_err(|e| FileIOError::from((aside, e)).into())
+}
+
+/// Finds a name for `path` that isn't taken yet, following `strategy`
+async fn find_available_path(path: &Path, strategy: RenameStrategy) -> Result<PathBuf, JobError> {
+	let timestamp = Utc::now().format("%Y-%m-%d %H.%M.%S").to_string();
+
+	let mut attempt = 1_u64;
+	loop {
+		let suffix = match strategy {
+			RenameStrategy::Incrementing => attempt.to_string(),
+			RenameStrategy::Timestamp if attempt == 1 => timestamp.clone(),
+			// two files can conflict within the same second, so they're numbered after the first
+			RenameStrategy::Timestamp => format!("{timestamp} {attempt}"),
+		};
+
+		let candidate = with_name_suffix(path, &suffix)?;
+		if fs::metadata(&candidate).await.is_err() {
+			return Ok(candidate);
+		}
+
+		attempt += 1;
+	}
+}
+
+/// Turns `dir/name.ext` into `dir/name (suffix).ext`
+fn with_name_suffix(path: &Path, suffix: &str) -> Result<PathBuf, JobError> {
+	let stem = path
+		.file_stem()
+		.ok_or(JobError::OsStr)?
+		.to_str()
+		.ok_or(JobError::OsStr)?;
+
+	let mut name = format!("{stem} ({suffix})");
+	if let Some(extension) = path.extension() {
+		name.push('.');
+		name.push_str(extension.to_str().ok_or(JobError::OsStr)?);
+	}
+
+	Ok(path.with_file_name(name))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn name_suffix_goes_before_extension() {
+		assert_eq!(
+			with_name_suffix(Path::new("/photos/beach.jpg"), "1").unwrap(),
+			Path::new("/photos/beach (1).jpg")
+		);
+		assert_eq!(
+			with_name_suffix(Path::new("/photos/Makefile"), "2").unwrap(),
+			Path::new("/photos/Makefile (2)")
+		);
+	}
+
+	#[tokio::test]
+	async fn replacing_keeps_only_the_new_contents() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+
+		fs::write(&source, "new").await.unwrap();
+		fs::write(&target, "old").await.unwrap();
+		replace(&source, &target).await.unwrap();
+		assert_eq!(fs::read_to_string(&target).await.unwrap(), "new");
+		assert!(!source.exists());
+
+		fs::create_dir_all(source.join("inner")).await.unwrap();
+		fs::remove_file(&target).await.unwrap();
+		fs::create_dir_all(target.join("stale")).await.unwrap();
+		replace(&source, &target).await.unwrap();
+		assert!(target.join("inner").is_dir());
+		assert!(!target.join("stale").exists());
+
+		let mut entries = fs::read_dir(dir.path()).await.unwrap();
+		let mut names = vec![];
+		while let Some(entry) = entries.next_entry().await.unwrap() {
+			names.push(entry.file_name());
+		}
+		assert_eq!(names, ["target"]);
+	}
+
+	#[tokio::test]
+	async fn incrementing_skips_taken_names() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("notes.txt");
+		fs::write(&path, "").await.unwrap();
+		fs::write(dir.path().join("notes (1).txt"), "")
+			.await
+			.unwrap();
+
+		assert_eq!(
+			find_available_path(&path, RenameStrategy::Incrementing)
+				.await
+				.unwrap(),
+			dir.path().join("notes (2).txt")
+		);
 	}
 }
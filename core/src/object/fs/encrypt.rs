@@ -83,6 +83,9 @@ impl StatefulJob for FileEncryptorJob {
 		if !info.path_data.is_dir {
 			// handle overwriting checks, and making sure there's enough available space
 
+			// expired keys have to be rotated before anything new is encrypted with them
+			key_manager.ensure_not_expired(state.init.key_uuid).await?;
+
 			let user_key = key_manager
 				.access_keymount(state.init.key_uuid)
 				.await?
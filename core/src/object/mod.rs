@@ -4,12 +4,14 @@ use serde::{Deserialize, Serialize};
 use specta::Type;
 
 pub mod cas;
+pub mod duplicates;
 pub mod file_identifier;
 pub mod fs;
 pub mod orphan_remover;
 pub mod preview;
 pub mod tag;
 pub mod validation;
+pub mod virtual_directory;
 
 // Objects are primarily created by the identifier from Paths
 // Some Objects are purely virtual, unless they have one or more associated Paths, which refer to a file found in a Location
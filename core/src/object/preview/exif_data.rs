@@ -0,0 +1,175 @@
+use crate::{
+	prisma::{exif_data, object, PrismaClient},
+	util::error::FileIOError,
+};
+
+use std::{
+	fs::File,
+	io::BufReader,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use exif::{Exif, In, Tag, Value};
+use thiserror::Error;
+use tokio::task::spawn_blocking;
+use tracing::{debug, error};
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ExifError {
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("failed to read the exif data: {0}")]
+	Exif(#[from] exif::Error),
+}
+
+/// ExifMetadata is the subset of a photo's EXIF data that Spacedrive indexes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExifMetadata {
+	/// When the photo was taken. This is in UTC if the camera didn't record its timezone
+	pub created_at: Option<DateTime<FixedOffset>>,
+	/// Decimal degrees, negative south of the equator
+	pub gps_latitude: Option<f64>,
+	/// Decimal degrees, negative west of the prime meridian
+	pub gps_longitude: Option<f64>,
+	pub camera_make: Option<String>,
+	pub camera_model: Option<String>,
+	/// The EXIF orientation, from 1 (upright) to 8
+	pub orientation: Option<u32>,
+}
+
+/// This reads the EXIF data of a JPEG, TIFF or HEIC file. It blocks while reading the file.
+pub fn extract_exif(path: &Path) -> Result<ExifMetadata, ExifError> {
+	let file = File::open(path).map_err(|e| FileIOError::from((path, e)))?;
+	let exif = exif::Reader::new().read_from_container(&mut BufReader::new(file))?;
+
+	Ok(ExifMetadata {
+		created_at: date_taken(&exif),
+		gps_latitude: gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S'),
+		gps_longitude: gps_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W'),
+		camera_make: ascii_field(&exif, Tag::Make),
+		camera_model: ascii_field(&exif, Tag::Model),
+		orientation: exif
+			.get_field(Tag::Orientation, In::PRIMARY)
+			.and_then(|field| field.value.get_uint(0)),
+	})
+}
+
+/// This extracts the EXIF data of newly identified objects and saves it to their `exif_data`.
+///
+/// Most files don't have EXIF data, so files it can't be read from are skipped without failing.
+pub async fn save_exif_data(
+	db: &PrismaClient,
+	objects: Vec<(Uuid, PathBuf)>,
+) -> Result<usize, prisma_client_rust::QueryError> {
+	if objects.is_empty() {
+		return Ok(0);
+	}
+
+	let mut extracted = Vec::with_capacity(objects.len());
+	for (object_pub_id, path) in objects {
+		match spawn_blocking(move || extract_exif(&path).map_err(|e| (path, e))).await {
+			Ok(Ok(metadata)) => extracted.push((object_pub_id, metadata)),
+			Ok(Err((path, e))) => debug!("No exif data extracted from {}: {e}", path.display()),
+			Err(e) => error!("Failed to join exif extraction task: {e:#?}"),
+		}
+	}
+
+	let object_ids = db
+		.object()
+		.find_many(vec![object::pub_id::in_vec(
+			extracted
+				.iter()
+				.map(|(pub_id, _)| pub_id.as_bytes().to_vec())
+				.collect(),
+		)])
+		.select(object::select!({ id pub_id }))
+		.exec()
+		.await?;
+
+	db.exif_data()
+		.create_many(
+			extracted
+				.into_iter()
+				.filter_map(|(pub_id, metadata)| {
+					object_ids
+						.iter()
+						.find(|object| object.pub_id == pub_id.as_bytes())
+						.map(|object| (object.id, metadata))
+				})
+				.map(|(id, metadata)| {
+					exif_data::create_unchecked(
+						id,
+						vec![
+							exif_data::date_taken::set(metadata.created_at),
+							exif_data::latitude::set(metadata.gps_latitude),
+							exif_data::longitude::set(metadata.gps_longitude),
+							exif_data::camera_make::set(metadata.camera_make),
+							exif_data::camera_model::set(metadata.camera_model),
+							exif_data::orientation::set(metadata.orientation.map(|o| o as i32)),
+						],
+					)
+				})
+				.collect(),
+		)
+		.exec()
+		.await
+		.map(|count| count as usize)
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+	match &exif.get_field(tag, In::PRIMARY)?.value {
+		Value::Ascii(values) => values
+			.first()
+			.map(|value| String::from_utf8_lossy(value).trim().to_string())
+			.filter(|value| !value.is_empty()),
+		_ => None,
+	}
+}
+
+fn date_taken(exif: &Exif) -> Option<DateTime<FixedOffset>> {
+	let Value::Ascii(values) = &exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?.value else {
+		return None;
+	};
+	let mut taken = exif::DateTime::from_ascii(values.first()?).ok()?;
+
+	if let Some(Value::Ascii(offsets)) = exif
+		.get_field(Tag::OffsetTimeOriginal, In::PRIMARY)
+		.map(|field| &field.value)
+	{
+		if let Some(offset) = offsets.first() {
+			// an unparseable offset is treated the same as a missing one
+			taken.parse_offset(offset).ok();
+		}
+	}
+
+	let offset = FixedOffset::east_opt(i32::from(taken.offset.unwrap_or(0)) * 60)?;
+
+	NaiveDate::from_ymd_opt(taken.year.into(), taken.month.into(), taken.day.into())?
+		.and_hms_opt(taken.hour.into(), taken.minute.into(), taken.second.into())?
+		.and_local_timezone(offset)
+		.single()
+}
+
+/// Reads a GPS coordinate, which EXIF stores as degrees, minutes and seconds plus a hemisphere reference
+fn gps_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: u8) -> Option<f64> {
+	let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+		return None;
+	};
+	let [degrees, minutes, seconds] = parts.as_slice() else {
+		return None;
+	};
+
+	let coordinate = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+	if !coordinate.is_finite() {
+		return None;
+	}
+
+	let is_negative = matches!(
+		exif.get_field(ref_tag, In::PRIMARY).map(|field| &field.value),
+		Some(Value::Ascii(refs)) if refs.first().and_then(|r| r.first()) == Some(&negative_ref)
+	);
+
+	Some(if is_negative { -coordinate } else { coordinate })
+}
@@ -1,5 +1,7 @@
+mod exif_data;
 mod media_data;
 mod thumbnail;
 
+pub use exif_data::*;
 pub use media_data::*;
 pub use thumbnail::*;
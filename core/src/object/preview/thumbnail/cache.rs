@@ -0,0 +1,285 @@
+use crate::util::error::FileIOError;
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use serde::Serialize;
+use tokio::{fs, io, sync::Mutex};
+use tracing::debug;
+
+use super::ThumbnailerError;
+
+/// The maximum size of the thumbnail cache, unless a different one is given to [`ThumbnailCache::load`]
+pub const DEFAULT_THUMBNAIL_CACHE_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// The fraction of `max_bytes` at which the cache is evicted from, and which eviction brings it back under
+const EVICTION_THRESHOLD: f64 = 0.95;
+
+/// ThumbnailCache keeps the size of the thumbnail directory under `max_bytes`, by evicting the thumbnails that were least recently accessed.
+///
+/// The thumbnail directory is shared by every library of the node, so there's one cache per node, which keeps track of every
+/// thumbnail in the directory. The sizes and access times are only kept in memory, and are read back from the files when the
+/// node starts, using the time a thumbnail was written as its last access.
+pub struct ThumbnailCache {
+	thumbnail_dir: PathBuf,
+	pub max_bytes: u64,
+	state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+	thumbnails: HashMap<String, CachedThumbnail>,
+	/// The combined size of `thumbnails`, kept up to date as they're recorded and evicted
+	total_bytes: u64,
+}
+
+struct CachedThumbnail {
+	size_in_bytes: u64,
+	last_accessed_at: SystemTime,
+}
+
+/// EvictionReport is what was removed from the cache by [`ThumbnailCache::evict_lru`].
+#[derive(Serialize, Debug, Default)]
+pub struct EvictionReport {
+	pub thumbnails_evicted: u32,
+	pub bytes_freed: u64,
+}
+
+impl ThumbnailCache {
+	/// This starts tracking every thumbnail that's already in `thumbnail_dir`, which doesn't have to exist yet
+	pub async fn load(
+		thumbnail_dir: impl Into<PathBuf>,
+		max_bytes: u64,
+	) -> Result<Self, ThumbnailerError> {
+		let thumbnail_dir = thumbnail_dir.into();
+		let mut state = CacheState::default();
+
+		let mut entries = match fs::read_dir(&thumbnail_dir).await {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				return Ok(Self {
+					thumbnail_dir,
+					max_bytes,
+					state: Mutex::new(state),
+				})
+			}
+			Err(e) => return Err(FileIOError::from((&thumbnail_dir, e)).into()),
+		};
+
+		while let Some(entry) = entries
+			.next_entry()
+			.await
+			.map_err(|e| FileIOError::from((&thumbnail_dir, e)))?
+		{
+			let path = entry.path();
+			let Some(cas_id) = cas_id_of(&path) else {
+				continue;
+			};
+			let metadata = entry
+				.metadata()
+				.await
+				.map_err(|e| FileIOError::from((&path, e)))?;
+			if !metadata.is_file() {
+				continue;
+			}
+
+			state.insert(
+				cas_id,
+				CachedThumbnail {
+					size_in_bytes: metadata.len(),
+					last_accessed_at: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+				},
+			);
+		}
+
+		Ok(Self {
+			thumbnail_dir,
+			max_bytes,
+			state: Mutex::new(state),
+		})
+	}
+
+	fn thumbnail_path(&self, cas_id: &str) -> PathBuf {
+		self.thumbnail_dir.join(format!("{cas_id}.webp"))
+	}
+
+	fn eviction_threshold(&self) -> u64 {
+		(self.max_bytes as f64 * EVICTION_THRESHOLD) as u64
+	}
+
+	/// Starts tracking the thumbnail of `cas_id`, which must already have been written to the thumbnail directory.
+	///
+	/// If this takes the cache over 95% of `max_bytes`, the least recently accessed thumbnails are evicted straight away and the report is returned.
+	/// Nothing is recorded if the thumbnail doesn't exist, e.g. because generating it failed.
+	pub async fn record(&self, cas_id: &str) -> Result<Option<EvictionReport>, ThumbnailerError> {
+		let path = self.thumbnail_path(cas_id);
+		let size_in_bytes = match fs::metadata(&path).await {
+			Ok(metadata) => metadata.len(),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(FileIOError::from((path, e)).into()),
+		};
+
+		let total_bytes = {
+			let mut state = self.state.lock().await;
+			state.insert(
+				cas_id.to_string(),
+				CachedThumbnail {
+					size_in_bytes,
+					last_accessed_at: SystemTime::now(),
+				},
+			);
+
+			state.total_bytes
+		};
+
+		if total_bytes < self.eviction_threshold() {
+			return Ok(None);
+		}
+
+		self.evict_lru().await.map(Some)
+	}
+
+	/// Marks the thumbnail of `cas_id` as just accessed, so it's the last to be evicted
+	pub async fn mark_accessed(&self, cas_id: &str) {
+		if let Some(thumbnail) = self.state.lock().await.thumbnails.get_mut(cas_id) {
+			thumbnail.last_accessed_at = SystemTime::now();
+		}
+	}
+
+	/// The combined size of every thumbnail that's tracked
+	pub async fn total_bytes(&self) -> u64 {
+		self.state.lock().await.total_bytes
+	}
+
+	/// Deletes the least recently accessed thumbnails until the cache is back under 95% of `max_bytes`.
+	///
+	/// Evicting to below the threshold that triggers eviction leaves room for new thumbnails, so the cache isn't evicted from again on every write.
+	pub async fn evict_lru(&self) -> Result<EvictionReport, ThumbnailerError> {
+		let mut report = EvictionReport::default();
+
+		let evicted = {
+			let mut state = self.state.lock().await;
+			let threshold = self.eviction_threshold();
+
+			let mut oldest = state
+				.thumbnails
+				.iter()
+				.map(|(cas_id, thumbnail)| (thumbnail.last_accessed_at, cas_id.clone()))
+				.collect::<Vec<_>>();
+			oldest.sort_unstable();
+
+			let mut evicted = vec![];
+			for (_, cas_id) in oldest {
+				if state.total_bytes < threshold {
+					break;
+				}
+
+				if let Some(thumbnail) = state.remove(&cas_id) {
+					report.bytes_freed += thumbnail.size_in_bytes;
+					report.thumbnails_evicted += 1;
+					evicted.push(cas_id);
+				}
+			}
+
+			evicted
+		};
+
+		for cas_id in evicted {
+			let path = self.thumbnail_path(&cas_id);
+			match fs::remove_file(&path).await {
+				Ok(()) => {}
+				// someone else already removed it
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(FileIOError::from((path, e)).into()),
+			}
+		}
+
+		debug!("Evicted thumbnails from the cache: {report:?}");
+
+		Ok(report)
+	}
+}
+
+impl CacheState {
+	fn insert(&mut self, cas_id: String, thumbnail: CachedThumbnail) {
+		self.total_bytes += thumbnail.size_in_bytes;
+		if let Some(replaced) = self.thumbnails.insert(cas_id, thumbnail) {
+			self.total_bytes -= replaced.size_in_bytes;
+		}
+	}
+
+	fn remove(&mut self, cas_id: &str) -> Option<CachedThumbnail> {
+		let thumbnail = self.thumbnails.remove(cas_id)?;
+		self.total_bytes -= thumbnail.size_in_bytes;
+
+		Some(thumbnail)
+	}
+}
+
+/// Thumbnails are named after the cas_id of the object they were generated for
+fn cas_id_of(path: &Path) -> Option<String> {
+	if path.extension()? != "webp" {
+		return None;
+	}
+
+	path.file_stem()?.to_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	async fn write_thumbnail(dir: &Path, cas_id: &str, size: usize) {
+		fs::write(dir.join(format!("{cas_id}.webp")), vec![0; size])
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn loading_tracks_existing_thumbnails() {
+		let dir = tempfile::tempdir().unwrap();
+		write_thumbnail(dir.path(), "a", 10).await;
+		write_thumbnail(dir.path(), "b", 20).await;
+		fs::write(dir.path().join("notes.txt"), "not a thumbnail")
+			.await
+			.unwrap();
+
+		let cache = ThumbnailCache::load(dir.path(), 1000).await.unwrap();
+		assert_eq!(cache.total_bytes().await, 30);
+
+		// recording a thumbnail again replaces its size instead of adding to it
+		write_thumbnail(dir.path(), "a", 15).await;
+		cache.record("a").await.unwrap();
+		assert_eq!(cache.total_bytes().await, 35);
+
+		assert!(ThumbnailCache::load(dir.path().join("missing"), 1000)
+			.await
+			.is_ok());
+	}
+
+	#[tokio::test]
+	async fn least_recently_accessed_thumbnails_are_evicted_first() {
+		let dir = tempfile::tempdir().unwrap();
+		let cache = ThumbnailCache::load(dir.path(), 100).await.unwrap();
+
+		for cas_id in ["a", "b", "c"] {
+			write_thumbnail(dir.path(), cas_id, 30).await;
+			assert!(cache.record(cas_id).await.unwrap().is_none());
+		}
+		cache.mark_accessed("a").await;
+
+		write_thumbnail(dir.path(), "d", 30).await;
+		let report = cache.record("d").await.unwrap().unwrap();
+
+		assert_eq!(report.thumbnails_evicted, 1);
+		assert_eq!(report.bytes_freed, 30);
+		assert_eq!(cache.total_bytes().await, 90);
+		assert!(!dir.path().join("b.webp").exists());
+		for cas_id in ["a", "c", "d"] {
+			assert!(dir.path().join(format!("{cas_id}.webp")).exists());
+		}
+	}
+}
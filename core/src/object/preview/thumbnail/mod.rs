@@ -32,9 +32,13 @@ use webp::Encoder;
 
 use self::thumbnailer_job::ThumbnailerJob;
 
+mod cache;
+mod progress;
 mod shallow;
 pub mod thumbnailer_job;
 
+pub use cache::*;
+pub use progress::*;
 pub use shallow::*;
 
 const THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
@@ -75,6 +79,9 @@ pub struct ThumbnailerJobState {
 	thumbnail_dir: PathBuf,
 	location_path: PathBuf,
 	report: ThumbnailerJobReport,
+	/// This job's share of the library's thumbnail progress, which is started again when a paused job is resumed
+	#[serde(skip)]
+	progress: Option<JobThumbnailProgress>,
 }
 
 #[derive(Error, Debug)]
@@ -228,6 +235,15 @@ async fn process_step(
 		.as_mut()
 		.expect("critical error: missing data on job state");
 
+	let remaining = state.steps.len() as u64;
+	let progress = data
+		.progress
+		.get_or_insert_with(|| ctx.library.thumbnail_progress.start(remaining));
+	progress.processing(data.location_path.join(IsolatedFilePathData::from((
+		state.init.location.id,
+		&step.file_path,
+	))));
+
 	let step_result = inner_process_step(
 		&step,
 		&data.location_path,
@@ -237,22 +253,26 @@ async fn process_step(
 	)
 	.await;
 
+	// a file we couldn't generate a thumbnail for is counted as failed, but doesn't fail the job
+	progress.finished(matches!(step_result, Ok(true)));
+
 	data.report.thumbnails_created += 1;
 
 	ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
 		state.step_number + 1,
 	)]);
 
-	step_result
+	step_result.map(|_| ())
 }
 
+/// This returns whether the file has a thumbnail now, failing to generate one is logged but isn't an error
 pub async fn inner_process_step(
 	step: &ThumbnailerJobStep,
 	location_path: &PathBuf,
 	thumbnail_dir: &PathBuf,
 	location: &location::Data,
 	library: &Library,
-) -> Result<(), JobError> {
+) -> Result<bool, JobError> {
 	let ThumbnailerJobStep { file_path, kind } = step;
 
 	// assemble the file path
@@ -266,7 +286,7 @@ pub async fn inner_process_step(
 			file_path.materialized_path
 		);
 
-		return Ok(());
+		return Ok(false);
 	};
 
 	// Define and write the WebP-encoded file to a given path
@@ -283,12 +303,14 @@ pub async fn inner_process_step(
 				ThumbnailerJobStepKind::Image => {
 					if let Err(e) = generate_image_thumbnail(&path, &output_path).await {
 						error!("Error generating thumb for image {:#?}", e);
+						return Ok(false);
 					}
 				}
 				#[cfg(feature = "ffmpeg")]
 				ThumbnailerJobStepKind::Video => {
 					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
 						error!("Error generating thumb for video: {:?} {:#?}", &path, e);
+						return Ok(false);
 					}
 				}
 			}
@@ -301,5 +323,12 @@ pub async fn inner_process_step(
 		Err(e) => return Err(ThumbnailerError::from(FileIOError::from((output_path, e))).into()),
 	}
 
-	Ok(())
+	// the cache is only housekeeping, so failing to track the thumbnail shouldn't fail the job
+	match library.thumbnail_cache.record(cas_id).await {
+		Ok(Some(report)) => info!("Thumbnail cache was full, evicted: {report:?}"),
+		Ok(None) => {}
+		Err(e) => error!("Failed to record thumbnail in the cache: {e:#?}"),
+	}
+
+	Ok(true)
 }
@@ -0,0 +1,224 @@
+use std::{
+	path::PathBuf,
+	sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use specta::Type;
+use tokio::sync::watch;
+
+/// ThumbnailProgress is how far the thumbnailer job of a library has gotten, as published by [`ThumbnailProgressTracker`].
+#[serde_as]
+#[derive(Serialize, Type, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThumbnailProgress {
+	/// How many files the job is going to generate thumbnails for
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub total: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub completed: u64,
+	/// How many files the thumbnail couldn't be generated for, which doesn't stop the job
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub failed: u64,
+	/// The file that's being processed right now, if any
+	pub current_path: Option<PathBuf>,
+}
+
+/// ThumbnailProgressTracker holds the latest [`ThumbnailProgress`] of a library, so anyone can watch it while the thumbnailer runs.
+///
+/// Thumbnailer jobs of the same library can run at the same time, so each one [`start`](Self::start)s its own
+/// [`JobThumbnailProgress`], and the progress that's published adds all of them up. It's only reset once every job is done.
+#[derive(Debug)]
+pub struct ThumbnailProgressTracker {
+	progress: watch::Sender<ThumbnailProgress>,
+	/// How many jobs are adding to the progress right now
+	running_jobs: Mutex<usize>,
+}
+
+impl ThumbnailProgressTracker {
+	pub fn new() -> Self {
+		Self {
+			progress: watch::channel(ThumbnailProgress::default()).0,
+			running_jobs: Mutex::new(0),
+		}
+	}
+
+	/// The receiver always holds the latest progress, intermediate updates are skipped if they come faster than they're read
+	pub fn subscribe(&self) -> watch::Receiver<ThumbnailProgress> {
+		self.progress.subscribe()
+	}
+
+	/// This adds a job that's about to process `total` files, resetting the progress first if no other job is running
+	pub(super) fn start(self: &Arc<Self>, total: u64) -> JobThumbnailProgress {
+		let mut running_jobs = self.running_jobs();
+		if *running_jobs == 0 {
+			self.progress.send_replace(ThumbnailProgress {
+				total,
+				..Default::default()
+			});
+		} else {
+			self.progress
+				.send_modify(|progress| progress.total += total);
+		}
+		*running_jobs += 1;
+
+		JobThumbnailProgress {
+			tracker: Arc::clone(self),
+			remaining: total,
+		}
+	}
+
+	fn running_jobs(&self) -> MutexGuard<'_, usize> {
+		// the count is always left consistent, so a panic while holding the lock can't corrupt it
+		self.running_jobs
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Default for ThumbnailProgressTracker {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// JobThumbnailProgress is the share of one job in the [`ThumbnailProgress`] of its library.
+///
+/// When it's dropped, the files the job didn't get to (e.g. because it failed or was paused) are taken back out of the total.
+#[derive(Debug)]
+pub struct JobThumbnailProgress {
+	tracker: Arc<ThumbnailProgressTracker>,
+	remaining: u64,
+}
+
+impl JobThumbnailProgress {
+	pub(super) fn processing(&self, path: PathBuf) {
+		self.tracker
+			.progress
+			.send_modify(|progress| progress.current_path = Some(path));
+	}
+
+	/// This counts the file that was being processed as completed or failed
+	pub(super) fn finished(&mut self, generated: bool) {
+		self.remaining = self.remaining.saturating_sub(1);
+		self.tracker.progress.send_modify(|progress| {
+			if generated {
+				progress.completed += 1;
+			} else {
+				progress.failed += 1;
+			}
+			progress.current_path = None;
+		});
+	}
+}
+
+impl Drop for JobThumbnailProgress {
+	fn drop(&mut self) {
+		let mut running_jobs = self.tracker.running_jobs();
+		*running_jobs = running_jobs.saturating_sub(1);
+
+		if self.remaining > 0 {
+			let remaining = self.remaining;
+			self.tracker.progress.send_modify(|progress| {
+				progress.total = progress.total.saturating_sub(remaining);
+			});
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn completed_only_increases() {
+		let tracker = Arc::new(ThumbnailProgressTracker::new());
+		let rx = tracker.subscribe();
+
+		let mut job = tracker.start(3);
+
+		let mut last = 0;
+		for path in ["a.png", "b.png", "c.png"] {
+			job.processing(path.into());
+			assert_eq!(rx.borrow().current_path, Some(PathBuf::from(path)));
+
+			job.finished(true);
+			let completed = rx.borrow().completed;
+			assert_eq!(completed, last + 1);
+			last = completed;
+		}
+
+		assert_eq!(
+			*rx.borrow(),
+			ThumbnailProgress {
+				total: 3,
+				completed: 3,
+				failed: 0,
+				current_path: None,
+			}
+		);
+	}
+
+	#[test]
+	fn failures_are_counted_without_stopping() {
+		let tracker = Arc::new(ThumbnailProgressTracker::new());
+		let rx = tracker.subscribe();
+
+		let mut job = tracker.start(3);
+		job.processing("a.png".into());
+		job.finished(true);
+		job.processing("broken.png".into());
+		job.finished(false);
+		job.processing("c.png".into());
+		job.finished(true);
+
+		let progress = rx.borrow().clone();
+		assert_eq!(progress.completed, 2);
+		assert_eq!(progress.failed, 1);
+		assert_eq!(progress.completed + progress.failed, progress.total);
+	}
+
+	#[test]
+	fn starting_once_every_job_is_done_resets_the_counts() {
+		let tracker = Arc::new(ThumbnailProgressTracker::new());
+		let rx = tracker.subscribe();
+
+		let mut job = tracker.start(1);
+		job.finished(false);
+		drop(job);
+		let _job = tracker.start(5);
+
+		assert_eq!(
+			*rx.borrow(),
+			ThumbnailProgress {
+				total: 5,
+				..Default::default()
+			}
+		);
+	}
+
+	#[test]
+	fn concurrent_jobs_add_up() {
+		let tracker = Arc::new(ThumbnailProgressTracker::new());
+		let rx = tracker.subscribe();
+
+		let mut first = tracker.start(2);
+		first.finished(true);
+		let mut second = tracker.start(3);
+		second.finished(true);
+
+		let progress = rx.borrow().clone();
+		assert_eq!((progress.completed, progress.total), (2, 5));
+
+		// the second job fails, so the files it didn't get to aren't waited on anymore
+		drop(second);
+		first.finished(true);
+		drop(first);
+
+		let progress = rx.borrow().clone();
+		assert_eq!((progress.completed, progress.total), (3, 3));
+	}
+}
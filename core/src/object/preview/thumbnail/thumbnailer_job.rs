@@ -16,11 +16,11 @@ use std::{collections::VecDeque, hash::Hash, path::PathBuf};
 use sd_file_ext::extensions::Extension;
 
 use serde::{Deserialize, Serialize};
-use tokio::fs;
+use tokio::{fs, sync::watch};
 use tracing::info;
 
 use super::{
-	finalize_thumbnailer, process_step, ThumbnailerError, ThumbnailerJobReport,
+	finalize_thumbnailer, process_step, ThumbnailProgress, ThumbnailerError, ThumbnailerJobReport,
 	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
 	THUMBNAIL_CACHE_DIR_NAME,
 };
@@ -30,6 +30,13 @@ use super::FILTERED_VIDEO_EXTENSIONS;
 
 pub struct ThumbnailerJob {}
 
+impl ThumbnailerJob {
+	/// The progress of the thumbnailer job of `library`, which keeps updating across jobs
+	pub fn progress(library: &Library) -> watch::Receiver<ThumbnailProgress> {
+		library.thumbnail_progress.subscribe()
+	}
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ThumbnailerJobInit {
 	pub location: location::Data,
@@ -139,6 +146,8 @@ impl StatefulJob for ThumbnailerJob {
 		#[cfg(not(feature = "ffmpeg"))]
 		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
 
+		let progress = ctx.library.thumbnail_progress.start(all_files.len() as u64);
+
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
 			JobReportUpdate::Message(format!("Preparing to process {} files", all_files.len())),
@@ -152,6 +161,7 @@ impl StatefulJob for ThumbnailerJob {
 				path,
 				thumbnails_created: 0,
 			},
+			progress: Some(progress),
 		});
 		state.steps.extend(all_files);
 
@@ -167,13 +177,15 @@ impl StatefulJob for ThumbnailerJob {
 	}
 
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		finalize_thumbnailer(
-			state
-				.data
-				.as_ref()
-				.expect("critical error: missing data on job state"),
-			ctx,
-		)
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		// every file has been processed by now, so this only lets the next job reset the progress
+		data.progress.take();
+
+		finalize_thumbnailer(data, ctx)
 	}
 }
 
@@ -0,0 +1,353 @@
+use crate::{
+	library::LibraryManagerError,
+	prisma::{file_path, object, tag, tag_on_object, PrismaClient},
+};
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+use specta::Type;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+object::select!(object_for_effective_tags {
+	id
+	tags: select { tag }
+	file_paths: select { location_id materialized_path }
+});
+tag_on_object::include!(tag_on_object_with_tag { tag });
+
+/// EffectiveTag is a tag that applies to an object, either because the object has it or because one of the directories it's in does.
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct EffectiveTag {
+	pub tag: tag::Data,
+	/// The id of the directory's `file_path` the tag was inherited from, or `None` if the object is tagged directly
+	pub inherited_from: Option<i32>,
+}
+
+/// TagInheritanceGraph computes the tags that apply to an object through the directories it's in.
+///
+/// The tags of each directory are cached after they're first read. The parent chain itself is read from `file_path` every time,
+/// so moving files around never leaves the cache stale, but changing the tags of an object has to be followed by [`invalidate_object`](Self::invalidate_object).
+#[derive(Debug, Default)]
+pub struct TagInheritanceGraph {
+	/// The object and tags of every directory that was looked at, by the id of its `file_path`
+	directory_tags: RwLock<HashMap<i32, (i32, Vec<tag::Data>)>>,
+}
+
+impl TagInheritanceGraph {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Drops the cached tags of the directories the object belongs to, e.g. after a tag was assigned to it or unassigned from it
+	pub async fn invalidate_object(&self, object_id: i32) {
+		self.directory_tags
+			.write()
+			.await
+			.retain(|_, (dir_object_id, _)| *dir_object_id != object_id);
+	}
+
+	/// Drops every cached tag, e.g. after a tag was renamed or deleted
+	pub async fn invalidate_all(&self) {
+		self.directory_tags.write().await.clear();
+	}
+
+	/// Returns the tags of the object, followed by the tags it inherits from the directories it's in, the closest directories first.
+	///
+	/// A tag is only returned once, from the closest place it comes from.
+	pub async fn effective_tags(
+		&self,
+		db: &PrismaClient,
+		object_id: Uuid,
+	) -> Result<Vec<EffectiveTag>, LibraryManagerError> {
+		let object = db
+			.object()
+			.find_unique(object::pub_id::equals(object_id.as_bytes().to_vec()))
+			.select(object_for_effective_tags::select())
+			.exec()
+			.await?
+			.ok_or(LibraryManagerError::ObjectNotFound(object_id))?;
+
+		let mut seen = HashSet::new();
+		let mut effective_tags = object
+			.tags
+			.into_iter()
+			.filter(|tag_on_object| seen.insert(tag_on_object.tag.id))
+			.map(|tag_on_object| EffectiveTag {
+				tag: tag_on_object.tag,
+				inherited_from: None,
+			})
+			.collect::<Vec<_>>();
+
+		for file_path in object.file_paths {
+			let ancestors = ancestor_dirs(&file_path.materialized_path);
+			if ancestors.is_empty() {
+				continue;
+			}
+
+			let mut dirs = db
+				.file_path()
+				.find_many(vec![
+					file_path::location_id::equals(file_path.location_id),
+					file_path::is_dir::equals(true),
+					file_path::materialized_path::in_vec(
+						ancestors.iter().map(|(path, _)| path.clone()).collect(),
+					),
+				])
+				.select(file_path::select!({ id materialized_path name object_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.filter(|dir| {
+					ancestors.contains(&(dir.materialized_path.clone(), dir.name.clone()))
+				})
+				.collect::<Vec<_>>();
+
+			// the closest directory has the longest path
+			dirs.sort_by(|a, b| b.materialized_path.len().cmp(&a.materialized_path.len()));
+
+			let dirs = dirs
+				.into_iter()
+				.filter_map(|dir| dir.object_id.map(|object_id| (dir.id, object_id)))
+				.collect::<Vec<_>>();
+
+			let tags = self.directory_tags(db, &dirs).await?;
+
+			for (dir_id, _) in dirs {
+				for tag in tags.get(&dir_id).into_iter().flatten() {
+					if seen.insert(tag.id) {
+						effective_tags.push(EffectiveTag {
+							tag: tag.clone(),
+							inherited_from: Some(dir_id),
+						});
+					}
+				}
+			}
+		}
+
+		Ok(effective_tags)
+	}
+
+	/// Returns the tags of each of the `(file_path id, object id)` directories, reading the ones that aren't cached yet
+	async fn directory_tags(
+		&self,
+		db: &PrismaClient,
+		dirs: &[(i32, i32)],
+	) -> Result<HashMap<i32, Vec<tag::Data>>, LibraryManagerError> {
+		let mut tags = HashMap::with_capacity(dirs.len());
+		let mut missing = Vec::new();
+
+		{
+			let cache = self.directory_tags.read().await;
+			for (dir_id, object_id) in dirs {
+				match cache.get(dir_id) {
+					Some((cached_object_id, dir_tags)) if cached_object_id == object_id => {
+						tags.insert(*dir_id, dir_tags.clone());
+					}
+					_ => missing.push((*dir_id, *object_id)),
+				}
+			}
+		}
+
+		if missing.is_empty() {
+			return Ok(tags);
+		}
+
+		let mut by_object = HashMap::<_, Vec<_>>::new();
+		for tag_on_object in db
+			.tag_on_object()
+			.find_many(vec![tag_on_object::object_id::in_vec(
+				missing.iter().map(|(_, object_id)| *object_id).collect(),
+			)])
+			.include(tag_on_object_with_tag::include())
+			.exec()
+			.await?
+		{
+			by_object
+				.entry(tag_on_object.object_id)
+				.or_default()
+				.push(tag_on_object.tag);
+		}
+
+		let mut cache = self.directory_tags.write().await;
+		for (dir_id, object_id) in missing {
+			let dir_tags = by_object.get(&object_id).cloned().unwrap_or_default();
+			cache.insert(dir_id, (object_id, dir_tags.clone()));
+			tags.insert(dir_id, dir_tags);
+		}
+
+		Ok(tags)
+	}
+}
+
+/// This returns the tags of an object, including the ones it inherits from the directories it's in, without caching anything.
+pub async fn effective_tags(
+	db: &PrismaClient,
+	object_id: Uuid,
+) -> Result<Vec<EffectiveTag>, LibraryManagerError> {
+	TagInheritanceGraph::new()
+		.effective_tags(db, object_id)
+		.await
+}
+
+/// Returns the `(materialized_path, name)` of every directory above the `materialized_path` of a file path, the closest first
+fn ancestor_dirs(materialized_path: &str) -> Vec<(String, String)> {
+	let components = materialized_path
+		.split('/')
+		.filter(|component| !component.is_empty())
+		.collect::<Vec<_>>();
+
+	(0..components.len())
+		.rev()
+		.map(|i| {
+			let parent = components[..i]
+				.iter()
+				.fold(String::from("/"), |path, component| {
+					format!("{path}{component}/")
+				});
+
+			(parent, components[i].to_string())
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::db::{test_db, uuid_to_bytes};
+
+	async fn create_object(db: &PrismaClient) -> object::Data {
+		db.object()
+			.create(uuid_to_bytes(Uuid::new_v4()), vec![])
+			.exec()
+			.await
+			.unwrap()
+	}
+
+	async fn create_file_path(
+		db: &PrismaClient,
+		location_id: i32,
+		(materialized_path, name): (&str, &str),
+		is_dir: bool,
+		object: &object::Data,
+	) -> file_path::Data {
+		db.file_path()
+			.create_unchecked(
+				uuid_to_bytes(Uuid::new_v4()),
+				location_id,
+				materialized_path.to_string(),
+				name.to_string(),
+				String::new(),
+				name.as_bytes().to_vec(),
+				vec![0; 8],
+				vec![
+					file_path::is_dir::set(is_dir),
+					file_path::object_id::set(Some(object.id)),
+				],
+			)
+			.exec()
+			.await
+			.unwrap()
+	}
+
+	#[test]
+	fn root_level_files_have_no_ancestors() {
+		assert!(ancestor_dirs("/").is_empty());
+		assert!(ancestor_dirs("").is_empty());
+	}
+
+	#[test]
+	fn ancestors_of_nested_paths_are_the_closest_first() {
+		assert_eq!(
+			ancestor_dirs("/photos/2023/summer/"),
+			[
+				("/photos/2023/".to_string(), "summer".to_string()),
+				("/photos/".to_string(), "2023".to_string()),
+				("/".to_string(), "photos".to_string()),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn assigning_and_unassigning_tags_needs_the_object_to_be_invalidated() {
+		let (_dir, db) = test_db().await;
+
+		let node = db
+			.node()
+			.create(uuid_to_bytes(Uuid::new_v4()), "node".to_string(), vec![])
+			.exec()
+			.await
+			.unwrap();
+		let location = db
+			.location()
+			.create_unchecked(
+				uuid_to_bytes(Uuid::new_v4()),
+				node.id,
+				"location".to_string(),
+				"/location".to_string(),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let dir_object = create_object(&db).await;
+		let dir = create_file_path(&db, location.id, ("/", "photos"), true, &dir_object).await;
+		let file_object = create_object(&db).await;
+		create_file_path(&db, location.id, ("/photos/", "beach"), false, &file_object).await;
+
+		let tag = db
+			.tag()
+			.create(
+				uuid_to_bytes(Uuid::new_v4()),
+				vec![tag::name::set(Some("Holidays".to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		let graph = TagInheritanceGraph::new();
+		let file_object_id = Uuid::from_slice(&file_object.pub_id).unwrap();
+		let inherited = || {
+			let (db, graph) = (&db, &graph);
+			async move {
+				graph
+					.effective_tags(db, file_object_id)
+					.await
+					.unwrap()
+					.into_iter()
+					.map(|effective_tag| (effective_tag.tag.id, effective_tag.inherited_from))
+					.collect::<Vec<_>>()
+			}
+		};
+
+		assert!(inherited().await.is_empty());
+
+		db.tag_on_object()
+			.create(
+				tag::id::equals(tag.id),
+				object::id::equals(dir_object.id),
+				vec![],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		// the tags of the directory were cached before it was tagged
+		assert!(inherited().await.is_empty());
+		graph.invalidate_object(dir_object.id).await;
+		assert_eq!(inherited().await, [(tag.id, Some(dir.id))]);
+
+		db.tag_on_object()
+			.delete(tag_on_object::tag_id_object_id(tag.id, dir_object.id))
+			.exec()
+			.await
+			.unwrap();
+
+		assert_eq!(inherited().await, [(tag.id, Some(dir.id))]);
+		graph.invalidate_object(dir_object.id).await;
+		assert!(inherited().await.is_empty());
+	}
+}
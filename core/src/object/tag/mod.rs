@@ -6,6 +6,10 @@ use uuid::Uuid;
 
 use crate::prisma::{tag, PrismaClient};
 
+mod inheritance;
+
+pub use inheritance::*;
+
 #[derive(Type, Deserialize)]
 pub struct Tag {
 	pub name: String,
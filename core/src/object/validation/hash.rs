@@ -1,9 +1,11 @@
 use blake3::Hasher;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::{fs, io::Read, path::Path};
 use tokio::{
 	fs::File,
 	io::{self, AsyncReadExt},
 };
+use tracing::warn;
 
 const BLOCK_LEN: usize = 1048576;
 
@@ -22,3 +24,136 @@ pub async fn file_checksum(path: impl AsRef<Path>) -> Result<String, io::Error>
 
 	Ok(hex.to_string())
 }
+
+/// The same as [`file_checksum`], but blocks the current thread while reading the file.
+///
+/// This is meant to be run with `spawn_blocking`, so that many files can be hashed at once without tying up the async runtime.
+pub fn file_checksum_blocking(path: impl AsRef<Path>) -> Result<String, io::Error> {
+	let mut reader = fs::File::open(path)?;
+	let mut context = Hasher::new();
+	let mut buffer = vec![0; BLOCK_LEN].into_boxed_slice();
+	loop {
+		let read_count = reader.read(&mut buffer)?;
+		if read_count == 0 {
+			break;
+		}
+		context.update(&buffer[..read_count]);
+	}
+	let hex = context.finalize().to_hex();
+
+	Ok(hex.to_string())
+}
+
+/// HashingConfig controls how hard hashing files is allowed to hit the disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash)]
+pub struct HashingConfig {
+	/// How many files are hashed at the same time
+	pub parallelism: usize,
+	/// The IO priority of the threads that read the files
+	pub io_priority: IoPriority,
+}
+
+impl Default for HashingConfig {
+	fn default() -> Self {
+		Self {
+			parallelism: 4,
+			io_priority: IoPriority::Low,
+		}
+	}
+}
+
+/// IoPriority is how the OS should schedule disk IO of the current thread, relative to everything else on the system.
+///
+/// It's applied with `ioprio_set` on Linux and `setiopolicy_np` on macOS, and ignored elsewhere.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IoPriority {
+	Low,
+	Normal,
+	High,
+}
+
+impl IoPriority {
+	/// Applies this priority to the current thread, returning a guard that puts it back to `Normal` when dropped.
+	///
+	/// Blocking threads are reused by tokio, so the priority mustn't outlive the task that asked for it.
+	/// Failing to set the priority only logs a warning, as it doesn't stop the IO from happening.
+	pub fn apply_to_current_thread(self) -> IoPriorityGuard {
+		if let Err(e) = set_current_thread_io_priority(self) {
+			warn!("Failed to set the IO priority of the current thread to {self:?}: {e}");
+		}
+
+		IoPriorityGuard(self)
+	}
+}
+
+/// Resets the IO priority of the current thread once it's dropped, see [`IoPriority::apply_to_current_thread`].
+pub struct IoPriorityGuard(IoPriority);
+
+impl Drop for IoPriorityGuard {
+	fn drop(&mut self) {
+		if self.0 != IoPriority::Normal {
+			if let Err(e) = set_current_thread_io_priority(IoPriority::Normal) {
+				warn!("Failed to reset the IO priority of the current thread: {e}");
+			}
+		}
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn set_current_thread_io_priority(priority: IoPriority) -> Result<(), io::Error> {
+	// from linux/ioprio.h
+	const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+	const IOPRIO_CLASS_BE: libc::c_int = 2;
+	const IOPRIO_CLASS_IDLE: libc::c_int = 3;
+	const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+
+	let ioprio = match priority {
+		IoPriority::Low => IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+		IoPriority::Normal => (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 4,
+		IoPriority::High => IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT,
+	};
+
+	// SAFETY: ioprio_set only reads its integer arguments, and a `who` of 0 with IOPRIO_WHO_PROCESS is the calling thread
+	let res = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+	if res == -1 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn set_current_thread_io_priority(priority: IoPriority) -> Result<(), io::Error> {
+	// from sys/resource.h
+	const IOPOL_TYPE_DISK: libc::c_int = 0;
+	const IOPOL_SCOPE_THREAD: libc::c_int = 1;
+	const IOPOL_IMPORTANT: libc::c_int = 1;
+	const IOPOL_THROTTLE: libc::c_int = 3;
+	const IOPOL_STANDARD: libc::c_int = 5;
+
+	extern "C" {
+		fn setiopolicy_np(
+			iotype: libc::c_int,
+			scope: libc::c_int,
+			policy: libc::c_int,
+		) -> libc::c_int;
+	}
+
+	let policy = match priority {
+		IoPriority::Low => IOPOL_THROTTLE,
+		IoPriority::Normal => IOPOL_STANDARD,
+		IoPriority::High => IOPOL_IMPORTANT,
+	};
+
+	// SAFETY: setiopolicy_np only reads its integer arguments
+	if unsafe { setiopolicy_np(IOPOL_TYPE_DISK, IOPOL_SCOPE_THREAD, policy) } == -1 {
+		return Err(io::Error::last_os_error());
+	}
+
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn set_current_thread_io_priority(_priority: IoPriority) -> Result<(), io::Error> {
+	Ok(())
+}
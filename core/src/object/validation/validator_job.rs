@@ -9,13 +9,17 @@ use crate::{
 	util::error::FileIOError,
 };
 
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::{sync::Semaphore, task::spawn_blocking};
 use tracing::info;
 
-use super::hash::file_checksum;
+use super::hash::{file_checksum_blocking, HashingConfig};
+
+/// How many files are hashed in each step of the job
+const CHUNK_SIZE: usize = 100;
 
 // The Validator is able to:
 // - generate a full byte checksum for Objects in a Location
@@ -29,12 +33,41 @@ pub struct ObjectValidatorJobState {
 	pub task_count: usize,
 }
 
+/// ObjectValidatorStep is what the job hashes in one step.
+///
+/// Jobs from before files were hashed in batches have a single file in each step, which they're still resumed with.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ObjectValidatorStep {
+	Batch(Vec<file_path_for_object_validator::Data>),
+	File(file_path_for_object_validator::Data),
+}
+
+impl ObjectValidatorStep {
+	fn file_paths(&self) -> &[file_path_for_object_validator::Data] {
+		match self {
+			Self::Batch(file_paths) => file_paths,
+			Self::File(file_path) => std::slice::from_ref(file_path),
+		}
+	}
+
+	/// How many files have been hashed once this step, the `step_number`th one, is done
+	fn completed_task_count(&self, step_number: usize) -> usize {
+		match self {
+			Self::Batch(file_paths) => step_number * CHUNK_SIZE + file_paths.len(),
+			Self::File(_) => step_number + 1,
+		}
+	}
+}
+
 // The validator can
 #[derive(Serialize, Deserialize, Debug, Hash)]
 pub struct ObjectValidatorJobInit {
 	pub location_id: i32,
 	pub path: PathBuf,
 	pub background: bool,
+	#[serde(default)]
+	pub hashing: HashingConfig,
 }
 
 impl JobInitData for ObjectValidatorJobInit {
@@ -45,7 +78,7 @@ impl JobInitData for ObjectValidatorJobInit {
 impl StatefulJob for ObjectValidatorJob {
 	type Init = ObjectValidatorJobInit;
 	type Data = ObjectValidatorJobState;
-	type Step = file_path_for_object_validator::Data;
+	type Step = ObjectValidatorStep;
 
 	const NAME: &'static str = "object_validator";
 
@@ -56,16 +89,22 @@ impl StatefulJob for ObjectValidatorJob {
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
+		let file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(state.init.location_id),
+				file_path::is_dir::equals(false),
+				file_path::integrity_checksum::equals(None),
+			])
+			.select(file_path_for_object_validator::select())
+			.exec()
+			.await?;
+		let task_count = file_paths.len();
+
 		state.steps.extend(
-			db.file_path()
-				.find_many(vec![
-					file_path::location_id::equals(state.init.location_id),
-					file_path::is_dir::equals(false),
-					file_path::integrity_checksum::equals(None),
-				])
-				.select(file_path_for_object_validator::select())
-				.exec()
-				.await?,
+			file_paths
+				.chunks(CHUNK_SIZE)
+				.map(|chunk| ObjectValidatorStep::Batch(chunk.to_vec())),
 		);
 
 		let location = db
@@ -80,10 +119,10 @@ impl StatefulJob for ObjectValidatorJob {
 
 		state.data = Some(ObjectValidatorJobState {
 			root_path: location.path.into(),
-			task_count: state.steps.len(),
+			task_count,
 		});
 
-		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		ctx.progress(vec![JobReportUpdate::TaskCount(task_count)]);
 
 		Ok(())
 	}
@@ -95,24 +134,52 @@ impl StatefulJob for ObjectValidatorJob {
 	) -> Result<(), JobError> {
 		let Library { db, sync, .. } = &ctx.library;
 
-		let file_path = &state.steps[0];
+		let step = &state.steps[0];
+		let file_paths = step.file_paths();
 		let data = state
 			.data
 			.as_ref()
 			.expect("critical error: missing data on job state");
+		let HashingConfig {
+			parallelism,
+			io_priority,
+		} = state.init.hashing;
+
+		// permits are taken before spawning, so no more than `parallelism` files are ever being read at once
+		let semaphore = Arc::new(Semaphore::new(parallelism.max(1)));
+		let mut hashes = Vec::with_capacity(file_paths.len());
 
 		// this is to skip files that already have checksums
 		// i'm unsure what the desired behaviour is in this case
 		// we can also compare old and new checksums here
-		// This if is just to make sure, we already queried objects where integrity_checksum is null
-		if file_path.integrity_checksum.is_none() {
+		// This filter is just to make sure, we already queried objects where integrity_checksum is null
+		for file_path in file_paths
+			.iter()
+			.filter(|file_path| file_path.integrity_checksum.is_none())
+		{
 			let path = data.root_path.join(IsolatedFilePathData::from((
 				file_path.location.id,
 				file_path,
 			)));
-			let checksum = file_checksum(&path)
+			let permit = semaphore
+				.clone()
+				.acquire_owned()
 				.await
-				.map_err(|e| FileIOError::from((path, e)))?;
+				.expect("the semaphore is never closed");
+
+			hashes.push((
+				file_path,
+				spawn_blocking(move || {
+					let _permit = permit;
+					let _priority = io_priority.apply_to_current_thread();
+
+					file_checksum_blocking(&path).map_err(|e| FileIOError::from((path, e)))
+				}),
+			));
+		}
+
+		for (file_path, hash) in hashes {
+			let checksum = hash.await??;
 
 			sync.write_op(
 				db,
@@ -132,7 +199,8 @@ impl StatefulJob for ObjectValidatorJob {
 		}
 
 		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
-			state.step_number + 1,
+			step.completed_task_count(state.step_number)
+				.min(data.task_count),
 		)]);
 
 		Ok(())
@@ -152,3 +220,45 @@ impl StatefulJob for ObjectValidatorJob {
 		Ok(Some(serde_json::to_value(&state.init)?))
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::collections::VecDeque;
+
+	fn test_file_path(name: &str) -> file_path_for_object_validator::Data {
+		file_path_for_object_validator::Data {
+			pub_id: vec![0; 16],
+			materialized_path: "/".to_string(),
+			is_dir: false,
+			name: name.to_string(),
+			extension: "txt".to_string(),
+			integrity_checksum: None,
+			location: file_path_for_object_validator::location::Data {
+				id: 1,
+				pub_id: vec![1; 16],
+			},
+		}
+	}
+
+	#[test]
+	fn steps_of_single_files_are_still_resumed() {
+		let old_steps = VecDeque::from([test_file_path("a"), test_file_path("b")]);
+		let steps: VecDeque<ObjectValidatorStep> =
+			rmp_serde::from_slice(&rmp_serde::to_vec_named(&old_steps).unwrap()).unwrap();
+
+		assert!(matches!(&steps[1], ObjectValidatorStep::File(file_path) if file_path.name == "b"));
+		assert_eq!(steps[1].completed_task_count(1), 2);
+
+		let new_steps = VecDeque::from([ObjectValidatorStep::Batch(vec![
+			test_file_path("a"),
+			test_file_path("b"),
+		])]);
+		let steps: VecDeque<ObjectValidatorStep> =
+			rmp_serde::from_slice(&rmp_serde::to_vec_named(&new_steps).unwrap()).unwrap();
+
+		assert_eq!(steps[0].file_paths().len(), 2);
+		assert_eq!(steps[0].completed_task_count(0), 2);
+	}
+}
@@ -0,0 +1,526 @@
+use crate::{
+	library::LibraryManagerError,
+	prisma::{file_path, object, saved_search, tag, tag_on_object, PrismaClient, SortOrder},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use prisma_client_rust::{raw, PrismaValue};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+object::include!(object_with_file_paths { file_paths });
+
+/// VirtualDirectory is a view of the objects in a library that can be navigated like a directory, without the files being moved anywhere.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct VirtualDirectory {
+	pub id: Uuid,
+	pub kind: VirtualDirectoryKind,
+}
+
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub enum VirtualDirectoryKind {
+	/// The objects that have every one of the tags, with a subdirectory for each tag that narrows them down further.
+	/// Without any tags, this only has a subdirectory for every tag.
+	TagView(Vec<Uuid>),
+	/// The objects grouped by when they were created
+	DateView(DateGrouping),
+	/// The objects that match a query
+	SmartAlbum(SmartAlbumQuery),
+}
+
+/// DateGrouping is a level of a [`VirtualDirectoryKind::DateView`]. Dates are grouped in UTC.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+pub enum DateGrouping {
+	/// A subdirectory for every year objects were created in
+	Years,
+	/// A subdirectory for every month of `year` objects were created in
+	Months { year: i32 },
+	/// The objects created in `month` (1 to 12) of `year`
+	Month { year: i32, month: u32 },
+}
+
+/// SmartAlbumQuery is what a [`VirtualDirectoryKind::SmartAlbum`] lists, it matches the objects that meet every condition that's set.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmartAlbumQuery {
+	/// Any of these kinds (`sd_file_ext::kind::ObjectKind`), or any kind if it's empty
+	#[serde(default)]
+	pub kinds: Vec<i32>,
+	/// Every one of these tags
+	#[serde(default)]
+	pub tags: Vec<Uuid>,
+	pub favorite: Option<bool>,
+	pub hidden: Option<bool>,
+	pub created_after: Option<DateTime<Utc>>,
+	pub created_before: Option<DateTime<Utc>>,
+	/// Part of the name of one of the object's file paths
+	pub name_contains: Option<String>,
+}
+
+/// VirtualEntry is one of the entries of a [`VirtualDirectory`], which are listed by [`list_virtual_directory`].
+#[derive(Serialize, Type, Debug)]
+#[serde(tag = "type")]
+pub enum VirtualEntry {
+	File {
+		item: object_with_file_paths::Data,
+	},
+	Directory {
+		name: String,
+		directory: VirtualDirectory,
+	},
+}
+
+/// SavedVirtualDirectory is a [`VirtualDirectory`] that was saved as a search, under a name the user gave it.
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct SavedVirtualDirectory {
+	pub name: String,
+	pub directory: VirtualDirectory,
+}
+
+impl VirtualDirectory {
+	/// Creates a virtual directory whose id is derived from its kind, so that navigating to the same view twice gives the same directory
+	pub fn new(kind: VirtualDirectoryKind) -> Self {
+		let json = serde_json::to_vec(&kind).expect("virtual directory kinds always serialize");
+		let hash = blake3::hash(&json);
+		let mut id = [0; 16];
+		id.copy_from_slice(&hash.as_bytes()[..16]);
+
+		Self {
+			id: Uuid::from_bytes(id),
+			kind,
+		}
+	}
+}
+
+/// The creation date of an object as an SQLite datetime in UTC.
+///
+/// Prisma writes dates as milliseconds since the epoch, while the ones SQLite defaulted to `CURRENT_TIMESTAMP` are text, so both are handled.
+const DATE_CREATED_UTC: &str = "CASE typeof(date_created) \
+	WHEN 'integer' THEN datetime(date_created / 1000, 'unixepoch') \
+	ELSE datetime(date_created) END";
+
+/// This lists a page of the entries of a virtual directory, its subdirectories first and then its files.
+///
+/// `skip` and `take` only page through the files. The subdirectories are all listed on the first page (when `skip` is 0), on top of the files.
+pub async fn list_virtual_directory(
+	db: &PrismaClient,
+	vdir: &VirtualDirectory,
+	skip: usize,
+	take: usize,
+) -> Result<Vec<VirtualEntry>, LibraryManagerError> {
+	let page = Page { skip, take };
+
+	match &vdir.kind {
+		VirtualDirectoryKind::TagView(tags) => list_tag_view(db, tags, page).await,
+		VirtualDirectoryKind::DateView(grouping) => list_date_view(db, grouping, page).await,
+		VirtualDirectoryKind::SmartAlbum(query) => {
+			list_files(db, smart_album_filter(query), page).await
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Page {
+	skip: usize,
+	take: usize,
+}
+
+impl Page {
+	fn is_first(&self) -> bool {
+		self.skip == 0
+	}
+}
+
+/// This saves a virtual directory as a search, replacing the saved search with the same id if there's one
+pub async fn save_virtual_directory(
+	db: &PrismaClient,
+	name: String,
+	vdir: &VirtualDirectory,
+) -> Result<(), LibraryManagerError> {
+	let pub_id = vdir.id.as_bytes().to_vec();
+	let view = serde_json::to_string(&vdir.kind)?;
+
+	db.saved_search()
+		.upsert(
+			saved_search::pub_id::equals(pub_id.clone()),
+			saved_search::create(pub_id, name.clone(), view.clone(), vec![]),
+			vec![
+				saved_search::name::set(name),
+				saved_search::view::set(view),
+				saved_search::date_modified::set(Utc::now().into()),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// This lists every virtual directory that was saved as a search, in the order they were saved in
+pub async fn list_saved_virtual_directories(
+	db: &PrismaClient,
+) -> Result<Vec<SavedVirtualDirectory>, LibraryManagerError> {
+	db.saved_search()
+		.find_many(vec![])
+		.order_by(saved_search::id::order(SortOrder::Asc))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|search| {
+			Ok::<_, LibraryManagerError>(SavedVirtualDirectory {
+				name: search.name,
+				directory: VirtualDirectory {
+					id: Uuid::from_slice(&search.pub_id)?,
+					kind: serde_json::from_str(&search.view)?,
+				},
+			})
+		})
+		.collect()
+}
+
+async fn list_tag_view(
+	db: &PrismaClient,
+	tags: &[Uuid],
+	page: Page,
+) -> Result<Vec<VirtualEntry>, LibraryManagerError> {
+	if tags.is_empty() {
+		if !page.is_first() {
+			return Ok(vec![]);
+		}
+
+		let tags = db
+			.tag()
+			.find_many(vec![])
+			.order_by(tag::name::order(SortOrder::Asc))
+			.exec()
+			.await?;
+
+		return tags
+			.into_iter()
+			.map(|tag| Ok(tag_directory(tags_with(&[], &tag.pub_id)?, tag)))
+			.collect();
+	}
+
+	let mut entries = vec![];
+
+	if page.is_first() {
+		// the tags that some of the files have besides the ones of the view narrow it down further
+		let narrowing_tags = db
+			.tag()
+			.find_many(vec![
+				tag::pub_id::not_in_vec(tags.iter().map(|tag| tag.as_bytes().to_vec()).collect()),
+				tag::tag_objects::some(vec![tag_on_object::object::is(has_every_tag(tags))]),
+			])
+			.order_by(tag::name::order(SortOrder::Asc))
+			.exec()
+			.await?;
+
+		for tag in narrowing_tags {
+			entries.push(tag_directory(tags_with(tags, &tag.pub_id)?, tag));
+		}
+	}
+
+	entries.extend(list_files(db, has_every_tag(tags), page).await?);
+
+	Ok(entries)
+}
+
+fn tags_with(tags: &[Uuid], pub_id: &[u8]) -> Result<Vec<Uuid>, LibraryManagerError> {
+	let mut tags = tags.to_vec();
+	tags.push(Uuid::from_slice(pub_id)?);
+
+	Ok(tags)
+}
+
+fn tag_directory(tags: Vec<Uuid>, tag: tag::Data) -> VirtualEntry {
+	VirtualEntry::Directory {
+		name: tag.name.unwrap_or_default(),
+		directory: VirtualDirectory::new(VirtualDirectoryKind::TagView(tags)),
+	}
+}
+
+async fn list_date_view(
+	db: &PrismaClient,
+	grouping: &DateGrouping,
+	page: Page,
+) -> Result<Vec<VirtualEntry>, LibraryManagerError> {
+	match *grouping {
+		// these only have subdirectories
+		DateGrouping::Years | DateGrouping::Months { .. } if !page.is_first() => Ok(vec![]),
+		DateGrouping::Years => {
+			let years = creation_years(db).await?;
+
+			Ok(years
+				.into_iter()
+				.map(|year| VirtualEntry::Directory {
+					name: year.to_string(),
+					directory: VirtualDirectory::new(VirtualDirectoryKind::DateView(
+						DateGrouping::Months { year },
+					)),
+				})
+				.collect())
+		}
+		DateGrouping::Months { year } => {
+			let months = creation_months(db, year).await?;
+
+			Ok(months
+				.into_iter()
+				.map(|month| VirtualEntry::Directory {
+					name: format!("{year}-{month:02}"),
+					directory: VirtualDirectory::new(VirtualDirectoryKind::DateView(
+						DateGrouping::Month { year, month },
+					)),
+				})
+				.collect())
+		}
+		DateGrouping::Month { year, month } => {
+			let (next_year, next_month) = if month == 12 {
+				(year + 1, 1)
+			} else {
+				(year, month + 1)
+			};
+
+			list_files(
+				db,
+				created_between(month_start(year, month), month_start(next_year, next_month)),
+				page,
+			)
+			.await
+		}
+	}
+}
+
+/// The years objects were created in (in UTC), oldest first
+async fn creation_years(db: &PrismaClient) -> Result<Vec<i32>, LibraryManagerError> {
+	#[derive(Deserialize)]
+	struct Year {
+		year: i32,
+	}
+
+	Ok(db
+		._query_raw::<Year>(raw!(&format!(
+			"SELECT DISTINCT CAST(strftime('%Y', {DATE_CREATED_UTC}) AS INTEGER) AS year \
+			FROM object ORDER BY year"
+		)))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| row.year)
+		.collect())
+}
+
+/// The months (1 to 12) of `year` objects were created in (in UTC), in order
+async fn creation_months(db: &PrismaClient, year: i32) -> Result<Vec<u32>, LibraryManagerError> {
+	#[derive(Deserialize)]
+	struct Month {
+		month: u32,
+	}
+
+	Ok(db
+		._query_raw::<Month>(raw!(
+			&format!(
+				"SELECT DISTINCT CAST(strftime('%m', {DATE_CREATED_UTC}) AS INTEGER) AS month \
+				FROM object WHERE strftime('%Y', {DATE_CREATED_UTC}) = {{}} ORDER BY month"
+			),
+			PrismaValue::String(format!("{year:04}"))
+		))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| row.month)
+		.collect())
+}
+
+/// The first moment of `month` of `year` in UTC, or `None` if it can't be represented (which also makes an invalid month match nothing)
+fn month_start(year: i32, month: u32) -> Option<DateTime<Utc>> {
+	Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()
+}
+
+fn created_between(
+	start: Option<DateTime<Utc>>,
+	end: Option<DateTime<Utc>>,
+) -> Vec<object::WhereParam> {
+	match (start, end) {
+		(Some(start), Some(end)) => vec![
+			object::date_created::gte(start.into()),
+			object::date_created::lt(end.into()),
+		],
+		// an empty `in` matches nothing
+		_ => vec![object::id::in_vec(vec![])],
+	}
+}
+
+fn has_every_tag(tags: &[Uuid]) -> Vec<object::WhereParam> {
+	tags.iter()
+		.map(|tag| {
+			object::tags::some(vec![tag_on_object::tag::is(vec![tag::pub_id::equals(
+				tag.as_bytes().to_vec(),
+			)])])
+		})
+		.collect()
+}
+
+fn smart_album_filter(query: &SmartAlbumQuery) -> Vec<object::WhereParam> {
+	let mut filter = has_every_tag(&query.tags);
+
+	if !query.kinds.is_empty() {
+		filter.push(object::kind::in_vec(query.kinds.clone()));
+	}
+	if let Some(favorite) = query.favorite {
+		filter.push(object::favorite::equals(favorite));
+	}
+	if let Some(hidden) = query.hidden {
+		filter.push(object::hidden::equals(hidden));
+	}
+	if let Some(created_after) = query.created_after {
+		filter.push(object::date_created::gte(created_after.into()));
+	}
+	if let Some(created_before) = query.created_before {
+		filter.push(object::date_created::lt(created_before.into()));
+	}
+	if let Some(name) = &query.name_contains {
+		filter.push(object::file_paths::some(vec![file_path::name::contains(
+			name.clone(),
+		)]));
+	}
+
+	filter
+}
+
+async fn list_files(
+	db: &PrismaClient,
+	filter: Vec<object::WhereParam>,
+	page: Page,
+) -> Result<Vec<VirtualEntry>, LibraryManagerError> {
+	Ok(db
+		.object()
+		.find_many(filter)
+		.order_by(object::date_created::order(SortOrder::Desc))
+		.order_by(object::id::order(SortOrder::Desc))
+		.skip(page.skip as i64)
+		.take(page.take as i64)
+		.include(object_with_file_paths::include())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|item| VirtualEntry::File { item })
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::util::db::{test_db, uuid_to_bytes};
+
+	async fn create_object(db: &PrismaClient, date_created: DateTime<Utc>) -> object::Data {
+		db.object()
+			.create(
+				uuid_to_bytes(Uuid::new_v4()),
+				vec![object::date_created::set(date_created.into())],
+			)
+			.exec()
+			.await
+			.unwrap()
+	}
+
+	async fn create_tag(db: &PrismaClient, name: &str, objects: &[&object::Data]) -> tag::Data {
+		let tag = db
+			.tag()
+			.create(
+				uuid_to_bytes(Uuid::new_v4()),
+				vec![tag::name::set(Some(name.to_string()))],
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		if objects.is_empty() {
+			return tag;
+		}
+
+		db.tag_on_object()
+			.create_many(
+				objects
+					.iter()
+					.map(|object| tag_on_object::create_unchecked(tag.id, object.id, vec![]))
+					.collect(),
+			)
+			.exec()
+			.await
+			.unwrap();
+
+		tag
+	}
+
+	fn names(entries: &[VirtualEntry]) -> Vec<String> {
+		entries
+			.iter()
+			.map(|entry| match entry {
+				VirtualEntry::Directory { name, .. } => name.clone(),
+				VirtualEntry::File { item } => Uuid::from_slice(&item.pub_id).unwrap().to_string(),
+			})
+			.collect()
+	}
+
+	/// Files are named after the pub_id of their object in [`names`]
+	fn name_of(object: &object::Data) -> String {
+		Uuid::from_slice(&object.pub_id).unwrap().to_string()
+	}
+
+	fn date(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+	}
+
+	#[tokio::test]
+	async fn date_view_is_grouped_by_year_and_month() {
+		let (_dir, db) = test_db().await;
+		create_object(&db, date(2021, 3, 1)).await;
+		create_object(&db, date(2023, 6, 14)).await;
+		let newest = create_object(&db, date(2023, 6, 20)).await;
+		create_object(&db, date(2023, 11, 2)).await;
+
+		let list = |grouping, skip| {
+			let db = &db;
+			async move {
+				let vdir = VirtualDirectory::new(VirtualDirectoryKind::DateView(grouping));
+				list_virtual_directory(db, &vdir, skip, 1).await.unwrap()
+			}
+		};
+
+		assert_eq!(names(&list(DateGrouping::Years, 0).await), ["2021", "2023"]);
+		assert!(list(DateGrouping::Years, 1).await.is_empty());
+		assert_eq!(
+			names(&list(DateGrouping::Months { year: 2023 }, 0).await),
+			["2023-06", "2023-11"]
+		);
+
+		let june = DateGrouping::Month {
+			year: 2023,
+			month: 6,
+		};
+		assert_eq!(names(&list(june.clone(), 0).await), [name_of(&newest)]);
+		assert_eq!(list(june.clone(), 1).await.len(), 1);
+		assert!(list(june, 2).await.is_empty());
+	}
+
+	#[tokio::test]
+	async fn tag_view_is_narrowed_down_by_the_other_tags_of_its_objects() {
+		let (_dir, db) = test_db().await;
+		let both = create_object(&db, date(2023, 1, 2)).await;
+		let only_a = create_object(&db, date(2023, 1, 1)).await;
+		let only_b = create_object(&db, date(2023, 1, 3)).await;
+		let a = create_tag(&db, "a", &[&both, &only_a]).await;
+		create_tag(&db, "b", &[&both, &only_b]).await;
+		create_tag(&db, "unused", &[]).await;
+
+		let tags = vec![Uuid::from_slice(&a.pub_id).unwrap()];
+		let vdir = VirtualDirectory::new(VirtualDirectoryKind::TagView(tags));
+
+		let first_page = list_virtual_directory(&db, &vdir, 0, 1).await.unwrap();
+		assert_eq!(names(&first_page), ["b".to_string(), name_of(&both)]);
+
+		let second_page = list_virtual_directory(&db, &vdir, 1, 1).await.unwrap();
+		assert_eq!(names(&second_page), [name_of(&only_a)]);
+	}
+}
@@ -1,9 +1,11 @@
 mod p2p_manager;
 mod peer_metadata;
 mod protocol;
+mod transfer;
 
 pub use p2p_manager::*;
 pub use peer_metadata::*;
 pub use protocol::*;
+pub use transfer::*;
 
 pub(super) const SPACEDRIVE_APP_ID: &str = "spacedrive";
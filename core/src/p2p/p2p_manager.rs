@@ -3,8 +3,8 @@
 use std::{
 	borrow::Cow,
 	collections::HashMap,
-	path::PathBuf,
-	sync::Arc,
+	path::{Path, PathBuf},
+	sync::{Arc, PoisonError, RwLock},
 	time::{Duration, Instant},
 };
 
@@ -22,7 +22,7 @@ use tokio::{
 	sync::{broadcast, oneshot, Mutex},
 	time::sleep,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 use crate::{
@@ -30,7 +30,10 @@ use crate::{
 	p2p::{OperatingSystem, SPACEDRIVE_APP_ID},
 };
 
-use super::{Header, PeerMetadata};
+use super::{
+	receive_object, Header, LibraryHint, PeerMetadata, TransferError, TransferProgress,
+	TransferSummary,
+};
 
 /// The amount of time to wait for a Spacedrop request to be accepted or rejected before it's automatically rejected
 const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
@@ -48,6 +51,8 @@ pub enum P2PEvent {
 		peer_id: PeerId,
 		name: String,
 	},
+	/// A discovered peer has a library that this node also has, so the library can be synced with it
+	SyncCandidate { peer_id: PeerId, library_id: Uuid },
 	// TODO: Expire peer + connection/disconnect
 }
 
@@ -56,6 +61,8 @@ pub struct P2PManager {
 	pub manager: Arc<Manager<PeerMetadata>>,
 	spacedrop_pairing_reqs: Arc<Mutex<HashMap<Uuid, oneshot::Sender<Option<String>>>>>,
 	pub metadata_manager: Arc<MetadataManager<PeerMetadata>>,
+	/// The libraries this node has, which are only advertised as [`LibraryHint`]s
+	library_ids: Arc<RwLock<Vec<Uuid>>>,
 }
 
 impl P2PManager {
@@ -64,7 +71,8 @@ impl P2PManager {
 	) -> Result<(Arc<Self>, broadcast::Receiver<(Uuid, Vec<CRDTOperation>)>), ManagerError> {
 		let (config, keypair) = {
 			let config = node_config.get().await;
-			(Self::config_to_metadata(&config), config.keypair)
+			// the libraries are advertised once the library manager has loaded them
+			(Self::config_to_metadata(&config, vec![]), config.keypair)
 		};
 
 		let metadata_manager = MetadataManager::new(config);
@@ -82,10 +90,12 @@ impl P2PManager {
 		let (tx2, rx2) = broadcast::channel(100);
 
 		let spacedrop_pairing_reqs = Arc::new(Mutex::new(HashMap::new()));
+		let library_ids = Arc::new(RwLock::new(vec![]));
 		tokio::spawn({
 			let events = tx.clone();
 			// let sync_events = tx2.clone();
 			let spacedrop_pairing_reqs = spacedrop_pairing_reqs.clone();
+			let library_ids = library_ids.clone();
 
 			async move {
 				let mut shutdown = false;
@@ -105,6 +115,20 @@ impl P2PManager {
 								.map_err(|_| error!("Failed to send event to p2p event stream!"))
 								.ok();
 
+							let sync_candidates = Self::sync_candidates(
+								&library_ids.read().unwrap_or_else(PoisonError::into_inner),
+								event.peer_id,
+								&event.metadata,
+							);
+							for event in sync_candidates {
+								events
+									.send(event)
+									.map_err(|_| {
+										error!("Failed to send event to p2p event stream!")
+									})
+									.ok();
+							}
+
 							// TODO: Don't just connect to everyone when we find them. We should only do it if we know them.
 							// TODO(Spacedrop): Disable Spacedrop for now
 							// event.dial().await;
@@ -187,6 +211,14 @@ impl P2PManager {
 
 										sync_events.send((library_id, operations)).unwrap();
 									}
+									Header::ObjectTransfer {
+										library_id,
+										object_id,
+									} => {
+										// TODO: Serve the request with `send_library_object` once peers can be paired with a library.
+										// Until then any peer that can reach this node could download every file it has, so the stream is dropped.
+										warn!("Refused request of peer '{}' for object '{object_id}' of library '{library_id}', as it isn't paired with the library", event.peer_id);
+									}
 								}
 							});
 						}
@@ -215,6 +247,7 @@ impl P2PManager {
 			manager,
 			spacedrop_pairing_reqs,
 			metadata_manager,
+			library_ids,
 		});
 
 		// TODO: Probs remove this once connection timeout/keepalive are working correctly
@@ -231,19 +264,49 @@ impl P2PManager {
 		Ok((this, rx2))
 	}
 
-	fn config_to_metadata(config: &NodeConfig) -> PeerMetadata {
+	fn config_to_metadata(config: &NodeConfig, libraries: Vec<LibraryHint>) -> PeerMetadata {
 		PeerMetadata {
 			name: config.name.clone(),
 			operating_system: Some(OperatingSystem::get_os()),
 			version: Some(env!("CARGO_PKG_VERSION").to_string()),
 			email: config.p2p_email.clone(),
 			img_url: config.p2p_img_url.clone(),
+			libraries,
 		}
 	}
 
 	pub async fn update_metadata(&self, node_config_manager: &NodeConfigManager) {
-		self.metadata_manager
-			.update(Self::config_to_metadata(&node_config_manager.get().await));
+		self.metadata_manager.update(Self::config_to_metadata(
+			&node_config_manager.get().await,
+			self.metadata_manager.get().libraries,
+		));
+	}
+
+	/// Advertises the libraries this node has, so that peers which have them too can sync them with us
+	pub fn update_libraries(&self, library_ids: Vec<Uuid>) {
+		let mut metadata = self.metadata_manager.get();
+		metadata.libraries = library_ids.iter().copied().map(LibraryHint::new).collect();
+		*self
+			.library_ids
+			.write()
+			.unwrap_or_else(PoisonError::into_inner) = library_ids;
+		self.metadata_manager.update(metadata);
+	}
+
+	/// Returns a [`P2PEvent::SyncCandidate`] for every library of `library_ids` that the peer has too
+	pub fn sync_candidates(
+		library_ids: &[Uuid],
+		peer_id: PeerId,
+		remote: &PeerMetadata,
+	) -> Vec<P2PEvent> {
+		library_ids
+			.iter()
+			.filter(|library_id| remote.libraries.contains(&LibraryHint::new(**library_id)))
+			.map(|library_id| P2PEvent::SyncCandidate {
+				peer_id,
+				library_id: *library_id,
+			})
+			.collect()
 	}
 
 	pub async fn accept_spacedrop(&self, id: Uuid, path: String) {
@@ -332,6 +395,33 @@ impl P2PManager {
 		Ok(())
 	}
 
+	/// This asks `peer_id` for the file of the object with `object_id` in `library_id`, and writes it to `path`.
+	///
+	/// If `path` already holds part of the file from an earlier request, only the rest of it is sent.
+	#[allow(unused)] // TODO: Remove `allow(unused)` once integrated
+	pub async fn request_object(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		object_id: Uuid,
+		path: &Path,
+		progress: impl Fn(TransferProgress),
+	) -> Result<TransferSummary, TransferError> {
+		let mut stream = self
+			.manager
+			.stream(peer_id)
+			.await
+			.map_err(|_| TransferError::PeerUnreachable)?;
+
+		let header = Header::ObjectTransfer {
+			library_id,
+			object_id,
+		};
+		stream.write_all(&header.to_bytes()).await?;
+
+		receive_object(&mut stream, object_id, path, progress).await
+	}
+
 	pub async fn shutdown(&self) {
 		self.manager.shutdown().await;
 	}
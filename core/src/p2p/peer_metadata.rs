@@ -3,6 +3,11 @@ use std::{collections::HashMap, env, str::FromStr};
 use sd_p2p::Metadata;
 use serde::{Deserialize, Serialize};
 use specta::Type;
+use tracing::debug;
+use uuid::Uuid;
+
+/// The BLAKE3 key derivation context [`LibraryHint`]s are derived with
+const LIBRARY_HINT_CONTEXT: &str = "spacedrive 2023-06-13 p2p library hint";
 
 #[derive(Debug, Clone, Type, Serialize, Deserialize)]
 pub struct PeerMetadata {
@@ -11,11 +16,34 @@ pub struct PeerMetadata {
 	pub(super) version: Option<String>,
 	pub(super) email: Option<String>,
 	pub(super) img_url: Option<String>,
+	/// The libraries the peer has, so it can be offered as a sync candidate for the ones we have too
+	pub(super) libraries: Vec<LibraryHint>,
+}
+
+/// LibraryHint is how a peer advertises one of its libraries, without broadcasting the library's id over mDNS.
+///
+/// It's derived from the library id with BLAKE3, so it can't be turned back into the id,
+/// but a peer that has the library too can derive the same hint and tell they have it in common.
+#[derive(Debug, Clone, PartialEq, Eq, Type, Serialize, Deserialize)]
+pub struct LibraryHint(String);
+
+impl LibraryHint {
+	pub fn new(library_id: Uuid) -> Self {
+		let hint = blake3::derive_key(LIBRARY_HINT_CONTEXT, library_id.as_bytes());
+
+		Self(blake3::Hash::from(hint).to_hex().to_string())
+	}
+
+	/// This only accepts what [`LibraryHint::new`] could have produced, i.e. 64 lowercase hex characters
+	fn parse(hint: &str) -> Option<Self> {
+		(hint.len() == 64 && hint.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')))
+			.then(|| Self(hint.to_owned()))
+	}
 }
 
 impl Metadata for PeerMetadata {
 	fn to_hashmap(self) -> HashMap<String, String> {
-		let mut map = HashMap::with_capacity(3);
+		let mut map = HashMap::with_capacity(5 + self.libraries.len());
 		map.insert("name".to_owned(), self.name);
 		if let Some(os) = self.operating_system {
 			map.insert("os".to_owned(), os.to_string());
@@ -29,6 +57,10 @@ impl Metadata for PeerMetadata {
 		if let Some(img_url) = self.img_url {
 			map.insert("img_url".to_owned(), img_url);
 		}
+		// each entry of a TXT record is limited to 255 bytes, so every library gets its own
+		for (i, library) in self.libraries.into_iter().enumerate() {
+			map.insert(format!("library_{i}"), library.0);
+		}
 		map
 	}
 
@@ -51,6 +83,18 @@ impl Metadata for PeerMetadata {
 			version: data.get("version").map(|v| v.to_owned()),
 			email: data.get("email").map(|v| v.to_owned()),
 			img_url: data.get("img_url").map(|v| v.to_owned()),
+			// a malformed entry only means that library can't be synced with the peer, so the rest of it is still usable
+			libraries: data
+				.iter()
+				.filter(|(key, _)| key.starts_with("library_"))
+				.filter_map(|(key, hint)| {
+					let hint = LibraryHint::parse(hint);
+					if hint.is_none() {
+						debug!("Skipping malformed library hint '{key}' in peer metadata");
+					}
+					hint
+				})
+				.collect(),
 		})
 	}
 }
@@ -112,3 +156,33 @@ impl FromStr for OperatingSystem {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn malformed_library_hints_are_skipped() {
+		let library_id = Uuid::new_v4();
+		let mut data = PeerMetadata {
+			name: "peer".to_owned(),
+			operating_system: None,
+			version: None,
+			email: None,
+			img_url: None,
+			libraries: vec![LibraryHint::new(library_id)],
+		}
+		.to_hashmap();
+
+		// library ids are never advertised as they are
+		assert!(data
+			.values()
+			.all(|value| !value.contains(&library_id.to_string())));
+
+		data.insert("library_1".to_owned(), "not a hint".to_owned());
+		data.insert("library_2".to_owned(), Uuid::new_v4().to_string());
+
+		let metadata = PeerMetadata::from_hashmap(&data).unwrap();
+		assert_eq!(metadata.libraries, [LibraryHint::new(library_id)]);
+	}
+}
@@ -13,6 +13,12 @@ pub enum Header {
 	Ping,
 	Spacedrop(SpacedropRequest),
 	Sync(Uuid, u32),
+	/// Asks for the file of an object, which is then sent with [`send_object`](super::send_object).
+	/// Nodes refuse these for now, as peers can't be paired with a library yet
+	ObjectTransfer {
+		library_id: Uuid,
+		object_id: Uuid,
+	},
 }
 
 #[derive(Debug, Error)]
@@ -37,6 +43,10 @@ pub enum HeaderError {
 	SyncRequestError(#[from] SyncRequestError),
 	#[error("invalid request. Spacedrop requires a unicast stream!")]
 	SpacedropOverMulticastIsForbidden,
+	#[error("io error reading object transfer request: {0}")]
+	ObjectTransferIoError(std::io::Error),
+	#[error("invalid request. Object transfers require a unicast stream!")]
+	ObjectTransferOverMulticastIsForbidden,
 }
 
 impl Header {
@@ -73,6 +83,26 @@ impl Header {
 					len,
 				))
 			}
+			3 => match stream {
+				SpaceTimeStream::Unicast(stream) => {
+					let mut library_id = [0u8; 16];
+					stream
+						.read_exact(&mut library_id)
+						.await
+						.map_err(HeaderError::ObjectTransferIoError)?;
+					let mut object_id = [0u8; 16];
+					stream
+						.read_exact(&mut object_id)
+						.await
+						.map_err(HeaderError::ObjectTransferIoError)?;
+
+					Ok(Self::ObjectTransfer {
+						library_id: Uuid::from_bytes(library_id),
+						object_id: Uuid::from_bytes(object_id),
+					})
+				}
+				_ => Err(HeaderError::ObjectTransferOverMulticastIsForbidden),
+			},
 			d => Err(HeaderError::InvalidDiscriminator(d)),
 		}
 	}
@@ -95,6 +125,15 @@ impl Header {
 
 				bytes
 			}
+			Self::ObjectTransfer {
+				library_id,
+				object_id,
+			} => {
+				let mut bytes = vec![3];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes.extend_from_slice(object_id.as_bytes());
+				bytes
+			}
 		}
 	}
 }
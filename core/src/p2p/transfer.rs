@@ -0,0 +1,579 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_to_full_path, IsolatedFilePathData},
+	prisma::{file_path, location, object},
+	util::{db::uuid_to_bytes, error::FileIOError},
+};
+
+use std::path::Path;
+
+use prisma_client_rust::QueryError;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::{
+	fs::{File, OpenOptions},
+	io::{self, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, SeekFrom},
+};
+use tracing::debug;
+use uuid::Uuid;
+
+/// The version of the transfer protocol, which both ends send first so they can refuse a peer that speaks another one
+pub const TRANSFER_PROTOCOL_VERSION: u8 = 1;
+
+/// Files are sent in chunks of 1 MiB, which is also the granularity that transfers are resumed at
+pub const TRANSFER_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// TransferProgress is reported to the callback of [`send_object`] and [`receive_object`] after every chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransferProgress {
+	pub object_id: Uuid,
+	/// Includes the bytes the receiver already had when the transfer was resumed
+	pub transferred_bytes: u64,
+	pub total_bytes: u64,
+}
+
+/// TransferSummary describes a transfer that finished, and whose file matched its checksum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferSummary {
+	pub object_id: Uuid,
+	pub total_bytes: u64,
+	/// The chunk the transfer started from, which is only non-zero if it was resumed
+	pub resumed_from_chunk: u64,
+	/// How many bytes of the file went over the stream, excluding the ones the receiver already had
+	pub sent_bytes: u64,
+	pub sha256: [u8; 32],
+}
+
+#[derive(Error, Debug)]
+pub enum TransferError {
+	#[error(transparent)]
+	FileIO(#[from] FileIOError),
+	#[error("io error on the transfer stream: {0}")]
+	Stream(#[from] io::Error),
+	#[error("object has an invalid pub_id: {0}")]
+	InvalidObjectId(#[from] uuid::Error),
+	#[error("expected object '{expected}' but received object '{received}'")]
+	UnexpectedObject { expected: Uuid, received: Uuid },
+	#[error("expected chunk {expected} but received chunk {received}")]
+	UnexpectedChunk { expected: u64, received: u64 },
+	#[error("chunk {index} should be {expected} bytes but is {len}")]
+	InvalidChunkLength { index: u64, expected: u64, len: u32 },
+	#[error("the received file doesn't match its checksum")]
+	ChecksumMismatch,
+	#[error("the peer speaks version {0} of the transfer protocol, but version {TRANSFER_PROTOCOL_VERSION} is required")]
+	UnsupportedVersion(u8),
+	#[error("object '{0}' has no file on this node")]
+	ObjectNotFound(Uuid),
+	#[error("failed to open a stream to the peer")]
+	PeerUnreachable,
+	#[error(transparent)]
+	Database(#[from] QueryError),
+}
+
+/// The request the receiver opens a transfer with, saying which chunk it wants the file from.
+/// `prefix_sha256` is the checksum of the chunks before it, which it already has.
+struct ResumeRequest {
+	chunk_index: u64,
+	prefix_sha256: [u8; 32],
+}
+
+/// The header the sender answers a [`ResumeRequest`] with, before sending the chunks from `chunk_index` on.
+/// `chunk_index` is only the requested one if the receiver's chunks matched, and zero otherwise.
+struct TransferHeader {
+	object_id: Uuid,
+	total_size: u64,
+	sha256: [u8; 32],
+	chunk_index: u64,
+}
+
+/// This sends the file of the object with `object_id` in `library` to a [`receive_object`] on the other end of `stream`.
+///
+/// Only files in locations of this node are sent, and the object must have one that isn't a directory.
+#[allow(unused)] // TODO: Remove `allow(unused)` once peers can be paired with a library, so requests can be served
+pub async fn send_library_object(
+	library: &Library,
+	object_id: Uuid,
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	progress: impl Fn(TransferProgress),
+) -> Result<TransferSummary, TransferError> {
+	let object = library
+		.db
+		.object()
+		.find_unique(object::pub_id::equals(uuid_to_bytes(object_id)))
+		.exec()
+		.await?
+		.ok_or(TransferError::ObjectNotFound(object_id))?;
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::location::is(vec![location::node_id::equals(library.node_local_id)]),
+			file_path::object_id::equals(Some(object.id)),
+			file_path::is_dir::equals(false),
+		])
+		.select(file_path_to_full_path::select())
+		.exec()
+		.await?
+		.ok_or(TransferError::ObjectNotFound(object_id))?;
+
+	let path = Path::new(&file_path.location.path).join(IsolatedFilePathData::from((
+		file_path.location.id,
+		&file_path,
+	)));
+
+	send_object(stream, &object, &path, progress).await
+}
+
+/// This sends the file of `object` at `path` over `stream` to a [`receive_object`] on the other end.
+///
+/// The receiver asks for the chunk to start from, and if the chunks it already has match the file, only the rest is sent.
+/// Otherwise the whole file is sent again. Each chunk is sent as its index, its length and then its data,
+/// and the transfer only succeeds once the receiver confirms the SHA-256 of the whole file.
+/// The request and the header both start with [`TRANSFER_PROTOCOL_VERSION`], and either end fails with
+/// `TransferError::UnsupportedVersion` if the other one's doesn't match.
+pub async fn send_object(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	object: &object::Data,
+	path: &Path,
+	progress: impl Fn(TransferProgress),
+) -> Result<TransferSummary, TransferError> {
+	send_file(stream, Uuid::from_slice(&object.pub_id)?, path, progress).await
+}
+
+async fn send_file(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	object_id: Uuid,
+	path: &Path,
+	progress: impl Fn(TransferProgress),
+) -> Result<TransferSummary, TransferError> {
+	let mut file = File::open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	let total_size = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.len();
+
+	let request = ResumeRequest::from_stream(stream).await?;
+
+	// the receiver's chunks are checked while hashing the whole file, which only has to start over if they don't match
+	let requested_bytes = request.chunk_index.saturating_mul(TRANSFER_CHUNK_SIZE);
+	let mut hasher = Sha256::new();
+	let mut chunk_index = 0;
+	if request.chunk_index > 0 && requested_bytes <= total_size {
+		hash_from_file(&mut file, path, &mut hasher, requested_bytes).await?;
+		if <[u8; 32]>::from(hasher.clone().finalize()) == request.prefix_sha256 {
+			chunk_index = request.chunk_index;
+		}
+	}
+	let hashed_bytes = if chunk_index > 0 { requested_bytes } else { 0 };
+	if hashed_bytes == 0 {
+		hasher = Sha256::new();
+		seek(&mut file, path, 0).await?;
+	}
+	hash_from_file(&mut file, path, &mut hasher, total_size - hashed_bytes).await?;
+	let sha256 = hasher.finalize().into();
+
+	let header = TransferHeader {
+		object_id,
+		total_size,
+		sha256,
+		chunk_index,
+	};
+	stream.write_all(&header.to_bytes()).await?;
+
+	debug!("Sending object '{object_id}' from chunk {chunk_index}");
+
+	let start = chunk_index * TRANSFER_CHUNK_SIZE;
+	seek(&mut file, path, start).await?;
+
+	let mut buf = vec![0; TRANSFER_CHUNK_SIZE as usize];
+	let mut transferred_bytes = start;
+	for index in chunk_index..chunk_count(total_size) {
+		let len = chunk_len(total_size, index);
+		let data = &mut buf[..len as usize];
+		file.read_exact(data)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		stream.write_u64_le(index).await?;
+		stream.write_u32_le(len as u32).await?;
+		stream.write_all(data).await?;
+
+		transferred_bytes += len;
+		progress(TransferProgress {
+			object_id,
+			transferred_bytes,
+			total_bytes: total_size,
+		});
+	}
+	stream.flush().await?;
+
+	if stream.read_u8().await? != 1 {
+		return Err(TransferError::ChecksumMismatch);
+	}
+
+	Ok(TransferSummary {
+		object_id,
+		total_bytes: total_size,
+		resumed_from_chunk: chunk_index,
+		sent_bytes: total_size - start,
+		sha256,
+	})
+}
+
+/// This receives the file of the object with `object_id` that's sent by [`send_object`] over `stream`, writing it to `path`.
+///
+/// The transfer is refused if the sender answers with another object.
+/// If `path` already holds part of the file, e.g. from a transfer that was interrupted, the transfer is resumed after its last complete chunk.
+/// The sender checks those chunks against its file first, so a stale or unrelated file at `path` is overwritten instead.
+pub async fn receive_object(
+	stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+	object_id: Uuid,
+	path: &Path,
+	progress: impl Fn(TransferProgress),
+) -> Result<TransferSummary, TransferError> {
+	let mut file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create(true)
+		.open(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	let existing_size = file
+		.metadata()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?
+		.len();
+
+	// a partially received chunk is sent again, as there's no telling how much of it is intact
+	let complete_chunks = existing_size / TRANSFER_CHUNK_SIZE;
+	let mut hasher = Sha256::new();
+	hash_from_file(
+		&mut file,
+		path,
+		&mut hasher,
+		complete_chunks * TRANSFER_CHUNK_SIZE,
+	)
+	.await?;
+
+	let request = ResumeRequest {
+		chunk_index: complete_chunks,
+		prefix_sha256: hasher.clone().finalize().into(),
+	};
+	stream.write_all(&request.to_bytes()).await?;
+	stream.flush().await?;
+
+	let header = TransferHeader::from_stream(stream).await?;
+	if header.object_id != object_id {
+		return Err(TransferError::UnexpectedObject {
+			expected: object_id,
+			received: header.object_id,
+		});
+	}
+	let TransferHeader {
+		total_size,
+		chunk_index,
+		..
+	} = header;
+
+	if chunk_index != 0 && chunk_index != complete_chunks {
+		return Err(TransferError::UnexpectedChunk {
+			expected: complete_chunks,
+			received: chunk_index,
+		});
+	}
+	if chunk_index == 0 {
+		hasher = Sha256::new();
+	}
+
+	debug!("Receiving object '{object_id}' from chunk {chunk_index}");
+
+	let start = chunk_index * TRANSFER_CHUNK_SIZE;
+	file.set_len(start)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+	seek(&mut file, path, start).await?;
+
+	let mut buf = vec![0; TRANSFER_CHUNK_SIZE as usize];
+	let mut transferred_bytes = start;
+	for expected in chunk_index..chunk_count(total_size) {
+		let index = stream.read_u64_le().await?;
+		if index != expected {
+			return Err(TransferError::UnexpectedChunk {
+				expected,
+				received: index,
+			});
+		}
+
+		let len = stream.read_u32_le().await?;
+		let expected_len = chunk_len(total_size, index);
+		if u64::from(len) != expected_len {
+			return Err(TransferError::InvalidChunkLength {
+				index,
+				expected: expected_len,
+				len,
+			});
+		}
+
+		let data = &mut buf[..len as usize];
+		stream.read_exact(data).await?;
+		file.write_all(data)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		hasher.update(data);
+
+		transferred_bytes += u64::from(len);
+		progress(TransferProgress {
+			object_id,
+			transferred_bytes,
+			total_bytes: total_size,
+		});
+	}
+
+	file.sync_all()
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	let sha256 = <[u8; 32]>::from(hasher.finalize());
+	let matches = sha256 == header.sha256;
+	stream.write_u8(u8::from(matches)).await?;
+	stream.flush().await?;
+
+	if !matches {
+		return Err(TransferError::ChecksumMismatch);
+	}
+
+	Ok(TransferSummary {
+		object_id,
+		total_bytes: total_size,
+		resumed_from_chunk: chunk_index,
+		sent_bytes: total_size - start,
+		sha256,
+	})
+}
+
+impl ResumeRequest {
+	async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, TransferError> {
+		read_version(stream).await?;
+		let chunk_index = stream.read_u64_le().await?;
+		let mut prefix_sha256 = [0; 32];
+		stream.read_exact(&mut prefix_sha256).await?;
+
+		Ok(Self {
+			chunk_index,
+			prefix_sha256,
+		})
+	}
+
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut buf = Vec::with_capacity(1 + 8 + 32);
+		buf.push(TRANSFER_PROTOCOL_VERSION);
+		buf.extend_from_slice(&self.chunk_index.to_le_bytes());
+		buf.extend_from_slice(&self.prefix_sha256);
+		buf
+	}
+}
+
+impl TransferHeader {
+	async fn from_stream(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self, TransferError> {
+		read_version(stream).await?;
+		let mut object_id = [0; 16];
+		stream.read_exact(&mut object_id).await?;
+		let total_size = stream.read_u64_le().await?;
+		let mut sha256 = [0; 32];
+		stream.read_exact(&mut sha256).await?;
+		let chunk_index = stream.read_u64_le().await?;
+
+		Ok(Self {
+			object_id: Uuid::from_bytes(object_id),
+			total_size,
+			sha256,
+			chunk_index,
+		})
+	}
+
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut buf = Vec::with_capacity(1 + 16 + 8 + 32 + 8);
+		buf.push(TRANSFER_PROTOCOL_VERSION);
+		buf.extend_from_slice(self.object_id.as_bytes());
+		buf.extend_from_slice(&self.total_size.to_le_bytes());
+		buf.extend_from_slice(&self.sha256);
+		buf.extend_from_slice(&self.chunk_index.to_le_bytes());
+		buf
+	}
+}
+
+async fn read_version(stream: &mut (impl AsyncRead + Unpin)) -> Result<(), TransferError> {
+	match stream.read_u8().await? {
+		TRANSFER_PROTOCOL_VERSION => Ok(()),
+		version => Err(TransferError::UnsupportedVersion(version)),
+	}
+}
+
+fn chunk_count(total_size: u64) -> u64 {
+	(total_size + TRANSFER_CHUNK_SIZE - 1) / TRANSFER_CHUNK_SIZE
+}
+
+/// The length of chunk `index`, which is only shorter than [`TRANSFER_CHUNK_SIZE`] for the last chunk
+fn chunk_len(total_size: u64, index: u64) -> u64 {
+	(total_size - index * TRANSFER_CHUNK_SIZE).min(TRANSFER_CHUNK_SIZE)
+}
+
+async fn seek(file: &mut File, path: &Path, position: u64) -> Result<(), FileIOError> {
+	file.seek(SeekFrom::Start(position))
+		.await
+		.map(|_| ())
+		.map_err(|e| FileIOError::from((path, e)))
+}
+
+/// Feeds the next `len` bytes of `file` into `hasher`
+async fn hash_from_file(
+	file: &mut File,
+	path: &Path,
+	hasher: &mut Sha256,
+	len: u64,
+) -> Result<(), FileIOError> {
+	let mut buf = vec![0; TRANSFER_CHUNK_SIZE.min(len) as usize];
+	let mut remaining = len;
+
+	while remaining > 0 {
+		let read = TRANSFER_CHUNK_SIZE.min(remaining);
+		let data = &mut buf[..read as usize];
+		file.read_exact(data)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+		hasher.update(data);
+		remaining -= read;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use tempfile::TempDir;
+	use tokio::io::duplex;
+
+	fn test_file(dir: &TempDir) -> (std::path::PathBuf, Vec<u8>) {
+		let data = (0..TRANSFER_CHUNK_SIZE * 2 + 12345)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+		let path = dir.path().join("source.bin");
+		std::fs::write(&path, &data).unwrap();
+
+		(path, data)
+	}
+
+	async fn transfer(source: &Path, target: &Path) -> (TransferSummary, TransferSummary) {
+		let (mut sender, mut receiver) = duplex(64 * 1024);
+		let object_id = Uuid::new_v4();
+
+		let (sent, received) = tokio::join!(
+			send_file(&mut sender, object_id, source, |_| {}),
+			receive_object(&mut receiver, object_id, target, |_| {})
+		);
+
+		(sent.unwrap(), received.unwrap())
+	}
+
+	#[tokio::test]
+	async fn sends_whole_file() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, data) = test_file(&dir);
+		let target = dir.path().join("target.bin");
+
+		let (sent, received) = transfer(&source, &target).await;
+
+		assert_eq!(std::fs::read(&target).unwrap(), data);
+		assert_eq!(sent, received);
+		assert_eq!(received.resumed_from_chunk, 0);
+		assert_eq!(received.sent_bytes, data.len() as u64);
+		assert_eq!(
+			received.sha256,
+			<[u8; 32]>::from(Sha256::digest(data.as_slice()))
+		);
+	}
+
+	#[tokio::test]
+	async fn resumes_after_complete_chunks() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, data) = test_file(&dir);
+		let target = dir.path().join("target.bin");
+
+		// one complete chunk, plus part of the next one that has to be sent again
+		std::fs::write(&target, &data[..TRANSFER_CHUNK_SIZE as usize + 100]).unwrap();
+
+		let (_, received) = transfer(&source, &target).await;
+
+		assert_eq!(std::fs::read(&target).unwrap(), data);
+		assert_eq!(received.resumed_from_chunk, 1);
+		assert_eq!(received.sent_bytes, data.len() as u64 - TRANSFER_CHUNK_SIZE);
+	}
+
+	#[tokio::test]
+	async fn restarts_when_received_chunks_differ() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, data) = test_file(&dir);
+		let target = dir.path().join("target.bin");
+
+		let mut stale = data[..TRANSFER_CHUNK_SIZE as usize].to_vec();
+		stale[0] ^= 0xff;
+		std::fs::write(&target, stale).unwrap();
+
+		let (_, received) = transfer(&source, &target).await;
+
+		assert_eq!(std::fs::read(&target).unwrap(), data);
+		assert_eq!(received.resumed_from_chunk, 0);
+		assert_eq!(received.sent_bytes, data.len() as u64);
+	}
+
+	#[tokio::test]
+	async fn refuses_other_protocol_versions() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, _) = test_file(&dir);
+		let (mut sender, mut receiver) = duplex(64 * 1024);
+
+		let mut request = ResumeRequest {
+			chunk_index: 0,
+			prefix_sha256: [0; 32],
+		}
+		.to_bytes();
+		request[0] = TRANSFER_PROTOCOL_VERSION + 1;
+		receiver.write_all(&request).await.unwrap();
+
+		assert!(matches!(
+			send_file(&mut sender, Uuid::new_v4(), &source, |_| {}).await,
+			Err(TransferError::UnsupportedVersion(version)) if version == TRANSFER_PROTOCOL_VERSION + 1
+		));
+	}
+
+	#[tokio::test]
+	async fn refuses_another_object_than_requested() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, _) = test_file(&dir);
+		let (mut sender, mut receiver) = duplex(64 * 1024);
+		let (requested, sent) = (Uuid::new_v4(), Uuid::new_v4());
+
+		let (_, received) = tokio::join!(
+			send_file(&mut sender, sent, &source, |_| {}),
+			receive_object(
+				&mut receiver,
+				requested,
+				&dir.path().join("target.bin"),
+				|_| {}
+			)
+		);
+
+		assert!(matches!(
+			received,
+			Err(TransferError::UnexpectedObject { expected, received })
+				if expected == requested && received == sent
+		));
+	}
+}
@@ -0,0 +1,94 @@
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, Write},
+	path::Path,
+	sync::{Arc, Mutex, RwLock},
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// The sink audit events are delivered to. Events go to `tracing` until another sink is set with [`set_audit_sink`].
+static AUDIT_SINK: Lazy<RwLock<Arc<dyn AuditSink>>> =
+	Lazy::new(|| RwLock::new(Arc::new(TracingAuditSink)));
+
+/// AuditEvent is a security relevant action that was taken (or deliberately not taken) on behalf of the user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type")]
+pub enum AuditEvent {
+	/// A key was not written to the library
+	KeySkipped { uuid: Uuid, reason: SkipReason },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SkipReason {
+	/// The key is only meant to live in memory for the current session
+	MemoryOnly,
+}
+
+/// AuditSink receives every [`AuditEvent`] that's emitted.
+///
+/// `record` is called inline by whatever emitted the event, so it must be cheap and must not block for long.
+pub trait AuditSink: Send + Sync {
+	fn record(&self, event: &AuditEvent);
+}
+
+/// Logs audit events with `tracing`. This is the default sink.
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+	fn record(&self, event: &AuditEvent) {
+		info!(?event, "audit");
+	}
+}
+
+/// Appends audit events to a file, one JSON object per line.
+pub struct FileAuditSink(Mutex<File>);
+
+impl FileAuditSink {
+	pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+		OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)
+			.map(|file| Self(Mutex::new(file)))
+	}
+}
+
+impl AuditSink for FileAuditSink {
+	fn record(&self, event: &AuditEvent) {
+		let res = serde_json::to_string(event)
+			.map_err(io::Error::from)
+			.and_then(|line| match self.0.lock() {
+				Ok(mut file) => writeln!(file, "{line}"),
+				Err(_) => Err(io::Error::new(
+					io::ErrorKind::Other,
+					"audit log file lock was poisoned",
+				)),
+			});
+
+		if let Err(e) = res {
+			warn!("Failed to write audit event {event:?} to file: {e}");
+		}
+	}
+}
+
+/// Replaces the sink that audit events are delivered to
+pub fn set_audit_sink(sink: impl AuditSink + 'static) {
+	match AUDIT_SINK.write() {
+		Ok(mut current) => *current = Arc::new(sink),
+		Err(e) => *e.into_inner() = Arc::new(sink),
+	}
+}
+
+/// Delivers `event` to the current audit sink
+pub fn emit_audit_event(event: AuditEvent) {
+	let sink = match AUDIT_SINK.read() {
+		Ok(sink) => sink.clone(),
+		Err(e) => e.into_inner().clone(),
+	};
+
+	sink.record(&event);
+}
@@ -0,0 +1,29 @@
+/// CancellationToken is used to ask a long-running operation, like migrating a database, to stop at the next point where it safely can.
+///
+/// Clones share the same state, so cancelling any of them cancels all of them.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(tokio_util::sync::CancellationToken);
+
+impl CancellationToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn cancel(&self) {
+		self.0.cancel()
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.is_cancelled()
+	}
+
+	/// Waits until the token is cancelled
+	pub async fn cancelled(&self) {
+		self.0.cancelled().await
+	}
+
+	/// Creates a token that's cancelled along with this one, but can also be cancelled on its own
+	pub fn child_token(&self) -> Self {
+		Self(self.0.child_token())
+	}
+}
@@ -1,4 +1,6 @@
 mod abort_on_drop;
+pub mod audit;
+mod cancellation;
 pub mod db;
 #[cfg(debug_assertions)]
 pub mod debug_initializer;
@@ -6,3 +8,4 @@ pub mod error;
 pub mod migrator;
 
 pub use abort_on_drop::*;
+pub use cancellation::*;
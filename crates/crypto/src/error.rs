@@ -57,6 +57,8 @@ pub enum Error {
 	NoVerificationKey,
 	#[error("key isn't flagged as memory only")]
 	KeyNotMemoryOnly,
+	#[error("key has expired and needs to be rotated")]
+	KeyExpired,
 
 	// general errors
 	#[error("I/O error: {0}")]
@@ -37,6 +37,7 @@
 
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use tokio::sync::Mutex;
 
 use crate::{
@@ -77,6 +78,9 @@ pub struct StoredKey {
 	pub salt: Salt,
 	pub memory_only: bool,
 	pub automount: bool,
+	/// when the key has to be rotated by. expired keys can still decrypt, but can't be used to encrypt anything new
+	#[cfg_attr(feature = "serde", serde(default))]
+	pub expires_at: Option<DateTime<Utc>>,
 }
 
 /// This denotes the type of key. `Root` keys can be used to unlock the key manager, and `User` keys are ordinary keys.
@@ -96,6 +100,16 @@ pub enum StoredKeyVersion {
 	V1,
 }
 
+/// This upgrades a `StoredKey` that was created by an older version of Spacedrive to the latest `StoredKeyVersion`.
+///
+/// Keys that are already on the latest version are returned as they are. Every version is matched on explicitly,
+/// so that adding a new one won't compile until there's a migration path from the older ones.
+pub fn migrate_storedkey(key: StoredKey) -> Result<StoredKey> {
+	match key.version {
+		StoredKeyVersion::V1 => Ok(key),
+	}
+}
+
 /// This is a mounted key, and needs to be kept somewhat hidden.
 ///
 /// This contains the plaintext key, and the same key hashed with the content salt.
@@ -169,6 +183,14 @@ impl KeyManager {
 			.ok_or(Error::KeyAlreadyMounted)
 	}
 
+	/// This verifies that the target key hasn't expired, and can still be used to encrypt data.
+	pub async fn ensure_not_expired(&self, uuid: Uuid) -> Result<()> {
+		match self.access_keystore(uuid).await?.expires_at {
+			Some(expires_at) if expires_at <= Utc::now() => Err(Error::KeyExpired),
+			_ => Ok(()),
+		}
+	}
+
 	/// This verifies that the target key is not already mounted before continuing the operation.
 	pub fn ensure_not_mounted(&self, uuid: Uuid) -> Result<()> {
 		(!self.keymount.contains_key(&uuid))
@@ -321,6 +343,7 @@ impl KeyManager {
 			salt, // salt used for key derivation
 			memory_only: false,
 			automount: false,
+			expires_at: None,
 		};
 
 		*self.root_key.lock().await = Some(root_key);
@@ -449,6 +472,7 @@ impl KeyManager {
 			salt,
 			memory_only: false,
 			automount: false,
+			expires_at: None,
 		};
 
 		*self.verification_key.lock().await = Some(verification_key.clone());
@@ -870,6 +894,7 @@ impl KeyManager {
 				salt,
 				memory_only,
 				automount,
+				expires_at: None,
 			},
 		);
 
@@ -958,6 +983,26 @@ impl KeyManager {
 		Ok(())
 	}
 
+	/// This is for changing when a key in the keystore expires, `None` meaning it never does.
+	///
+	/// The database needs to be updated externally
+	pub async fn change_expiry(&self, uuid: Uuid, expires_at: Option<DateTime<Utc>>) -> Result<()> {
+		self.ensure_unlocked().await?;
+
+		let updated_key = self
+			.keystore
+			.get(&uuid)
+			.map_or(Err(Error::KeyNotFound), |v| {
+				let mut updated_key = v.clone();
+				updated_key.expires_at = expires_at;
+				Ok(updated_key)
+			})?;
+
+		self.keystore.remove(&uuid);
+		self.keystore.insert(uuid, updated_key);
+		Ok(())
+	}
+
 	/// This function is for getting an entire collection of hashed keys.
 	///
 	/// These are ideal for passing over to decryption functions, as each decryption attempt is negligible, performance wise.
@@ -104,7 +104,8 @@ export default (props: PropsWithChildren) => {
 									source_location_id: store.cutCopyState.sourceLocationId,
 									source_path_id: store.cutCopyState.sourcePathId,
 									target_location_id: store.locationId,
-									target_path: params.path
+									target_path: params.path,
+									conflict_resolution: null
 								});
 						}
 					}}
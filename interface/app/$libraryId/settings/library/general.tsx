@@ -1,4 +1,10 @@
-import { useBridgeMutation, useLibraryContext } from '@sd/client';
+import byteSize from 'byte-size';
+import {
+	useBridgeMutation,
+	useLibraryContext,
+	useLibraryMutation,
+	useLibraryQuery
+} from '@sd/client';
 import { Button, Input, dialogManager } from '@sd/ui';
 import { useZodForm, z } from '@sd/ui/src/forms';
 import { useDebouncedFormWatch } from '~/hooks';
@@ -15,6 +21,12 @@ const schema = z.object({
 export const Component = () => {
 	const { library } = useLibraryContext();
 	const editLibrary = useBridgeMutation('library.edit');
+	const dbStats = useLibraryQuery(['library.dbStats']);
+	const vacuum = useLibraryMutation('library.vacuum', {
+		onSuccess: () => dbStats.refetch()
+	});
+	// the health check reads the whole database, so it's only run when asked for
+	const healthCheck = useLibraryQuery(['library.healthCheck'], { enabled: false });
 
 	const form = useZodForm({
 		schema,
@@ -71,6 +83,54 @@ export const Component = () => {
 				</div>
 			</Setting> */}
 
+			{dbStats.data && (
+				<Setting
+					mini
+					title="Database"
+					description={`${dbStats.data.file_count} files, ${dbStats.data.object_count} objects and ${dbStats.data.tag_count} tags.`}
+				>
+					<div className="mt-2 text-right text-sm text-ink-dull">
+						<p>{byteSize(Number(dbStats.data.db_size_bytes)).toString()}</p>
+						<p>{dbStats.data.freelist_pages} free pages</p>
+					</div>
+					<div className="mt-2 ml-3">
+						<Button
+							size="sm"
+							variant="gray"
+							disabled={vacuum.isLoading}
+							onClick={() => vacuum.mutate(null)}
+						>
+							Compact
+						</Button>
+					</div>
+				</Setting>
+			)}
+
+			<Setting
+				mini
+				title="Database Health"
+				description={
+					healthCheck.data
+						? healthCheck.data.issues.length === 0
+							? 'No problems were found.'
+							: healthCheck.data.issues
+									.map((issue) => `${issue.severity}: ${issue.message}`)
+									.join('; ')
+						: 'Check the library database for corruption and missing data.'
+				}
+			>
+				<div className="mt-2">
+					<Button
+						size="sm"
+						variant="gray"
+						disabled={healthCheck.isFetching}
+						onClick={() => healthCheck.refetch()}
+					>
+						Check
+					</Button>
+				</div>
+			</Setting>
+
 			<Setting
 				mini
 				title="Delete Library"
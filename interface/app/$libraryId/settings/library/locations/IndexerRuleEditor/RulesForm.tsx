@@ -16,7 +16,12 @@ const ruleKinds: UnionToTuple<RuleKind> = [
 	'AcceptFilesByGlob',
 	'RejectFilesByGlob',
 	'AcceptIfChildrenDirectoriesArePresent',
-	'RejectIfChildrenDirectoriesArePresent'
+	'RejectIfChildrenDirectoriesArePresent',
+	'MinSize',
+	'MaxSize',
+	'MimeType',
+	'NameRegex',
+	'CreatedBefore'
 ];
 const ruleKindEnum = z.enum(ruleKinds);
 
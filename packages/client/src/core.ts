@@ -5,6 +5,7 @@ export type Procedures = {
     queries: 
         { key: "buildInfo", input: never, result: BuildInfo } | 
         { key: "categories.list", input: LibraryArgs<null>, result: { [key in Category]: number } } | 
+        { key: "files.findDuplicates", input: LibraryArgs<FindDuplicatesArgs>, result: DuplicatesPage } | 
         { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number; pub_id: number[]; kind: number; key_id: number | null; hidden: boolean; favorite: boolean; important: boolean; has_thumbnail: boolean; has_thumbstrip: boolean; has_video_preview: boolean; ipfs_id: string | null; note: string | null; date_created: string; date_accessed: string | null; file_paths: FilePath[]; media_data: MediaData | null } | null } | 
         { key: "invalidation.test-invalidate", input: never, result: number } | 
         { key: "jobs.getHistory", input: LibraryArgs<null>, result: JobReport[] } | 
@@ -17,6 +18,8 @@ export type Procedures = {
         { key: "keys.isUnlocked", input: LibraryArgs<null>, result: boolean } | 
         { key: "keys.list", input: LibraryArgs<null>, result: StoredKey[] } | 
         { key: "keys.listMounted", input: LibraryArgs<null>, result: string[] } | 
+        { key: "library.dbStats", input: LibraryArgs<null>, result: DbStats } | 
+        { key: "library.healthCheck", input: LibraryArgs<null>, result: HealthReport } | 
         { key: "library.list", input: never, result: LibraryConfigWrapped[] } | 
         { key: "library.statistics", input: LibraryArgs<null>, result: Statistics } | 
         { key: "locations.get", input: LibraryArgs<number>, result: Location | null } | 
@@ -28,8 +31,11 @@ export type Procedures = {
         { key: "nodeState", input: never, result: NodeState } | 
         { key: "search.objects", input: LibraryArgs<ObjectSearchArgs>, result: SearchData<ExplorerItem> } | 
         { key: "search.paths", input: LibraryArgs<FilePathSearchArgs>, result: SearchData<ExplorerItem> } | 
+        { key: "search.savedVirtualDirectories", input: LibraryArgs<null>, result: SavedVirtualDirectory[] } | 
+        { key: "search.virtualDirectory", input: LibraryArgs<VirtualDirectoryArgs>, result: VirtualEntry[] } | 
         { key: "sync.messages", input: LibraryArgs<null>, result: CRDTOperation[] } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
+        { key: "tags.getEffectiveForObject", input: LibraryArgs<string>, result: EffectiveTag[] } | 
         { key: "tags.getForObject", input: LibraryArgs<number>, result: Tag[] } | 
         { key: "tags.list", input: LibraryArgs<null>, result: Tag[] } | 
         { key: "volumes.list", input: never, result: Volume[] },
@@ -67,9 +73,11 @@ export type Procedures = {
         { key: "keys.unmount", input: LibraryArgs<string>, result: null } | 
         { key: "keys.unmountAll", input: LibraryArgs<null>, result: null } | 
         { key: "keys.updateAutomountStatus", input: LibraryArgs<AutomountUpdateArgs>, result: null } | 
+        { key: "keys.updateExpiry", input: LibraryArgs<ExpiryUpdateArgs>, result: null } | 
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
         { key: "library.delete", input: string, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
+        { key: "library.vacuum", input: LibraryArgs<null>, result: VacuumStats } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
@@ -81,13 +89,16 @@ export type Procedures = {
         { key: "nodes.changeNodeName", input: ChangeNodeNameArgs, result: NodeConfig } | 
         { key: "p2p.acceptSpacedrop", input: [string, string | null], result: null } | 
         { key: "p2p.spacedrop", input: SpacedropArgs, result: null } | 
+        { key: "search.saveVirtualDirectory", input: LibraryArgs<SaveVirtualDirectoryArgs>, result: null } | 
         { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: null } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
         { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null },
     subscriptions: 
         { key: "invalidation.listen", input: never, result: InvalidateOperationEvent[] } | 
+        { key: "jobs.indexingReport", input: LibraryArgs<null>, result: IndexingJobReport } | 
         { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string } | 
+        { key: "jobs.thumbnailProgress", input: LibraryArgs<null>, result: ThumbnailProgress } | 
         { key: "locations.online", input: never, result: number[][] } | 
         { key: "locations.quickRescan", input: LibraryArgs<LightScanArgs>, result: null } | 
         { key: "p2p.events", input: never, result: P2PEvent } | 
@@ -116,10 +127,32 @@ export type ChangeNodeNameArgs = { name: string }
 
 export type CreateLibraryArgs = { name: string }
 
+/**
+ * DbStats is a summary of what's in a library database and how much space it takes up on disk.
+ */
+export type DateGrouping = "Years" | { Months: { year: number } } | { Month: { year: number; month: number } }
+
+export type DbStats = { file_count: string; object_count: string; tag_count: string; location_count: string; key_count: string; page_count: string; db_size_bytes: string; file_size_bytes: string | null; freelist_pages: string }
+
 export type DiskType = "SSD" | "HDD" | "Removable"
 
+/**
+ * DuplicateGroup is a set of files in a location that have the same content, or most likely do.
+ */
+export type DuplicateGroup = { cas_id: string; integrity_checksum: string | null; paths: string[]; total_wasted_bytes: string }
+
+/**
+ * DuplicatesPage is a page of the [`DuplicateGroup`]s returned by [`find_duplicates`].
+ */
+export type DuplicatesPage = { groups: DuplicateGroup[]; total_groups: number; unhashed_files: number }
+
 export type EditLibraryArgs = { id: string; name: string | null; description: string | null }
 
+/**
+ * EffectiveTag is a tag that applies to an object, either because the object has it or because one of the directories it's in does.
+ */
+export type EffectiveTag = { tag: Tag; inherited_from: number | null }
+
 /**
  * This should be used for passing an encrypted key around.
  * 
@@ -127,11 +160,18 @@ export type EditLibraryArgs = { id: string; name: string | null; description: st
  */
 export type EncryptedKey = number[]
 
+export type ExpiryUpdateArgs = { uuid: string; expires_at: string | null }
+
 export type ExplorerItem = { type: "Path"; has_thumbnail: boolean; item: FilePathWithObject } | { type: "Object"; has_thumbnail: boolean; item: ObjectWithFilePaths }
 
 export type FileCopierJobInit = { source_location_id: number; source_path_id: number; target_location_id: number; target_path: string; target_file_name_suffix: string | null }
 
-export type FileCutterJobInit = { source_location_id: number; source_path_id: number; target_location_id: number; target_path: string }
+/**
+ * `FileConflictResolution` decides what happens to files that would overwrite an existing file when they're moved.
+ */
+export type FileConflictResolution = "Skip" | "Overwrite" | { Rename: RenameStrategy }
+
+export type FileCutterJobInit = { source_location_id: number; source_path_id: number; target_location_id: number; target_path: string; conflict_resolution: FileConflictResolution | null }
 
 export type FileDecryptorJobInit = { location_id: number; path_id: number; mount_associated_key: boolean; output_path: string | null; password: string | null; save_to_library: boolean | null }
 
@@ -151,6 +191,8 @@ export type FilePathSearchOrdering = { name: SortOrder } | { sizeInBytes: SortOr
 
 export type FilePathWithObject = { id: number; pub_id: number[]; is_dir: boolean; cas_id: string | null; integrity_checksum: string | null; location_id: number; materialized_path: string; name: string; extension: string; size_in_bytes: string; inode: number[]; device: number[]; object_id: number | null; key_id: number | null; date_created: string; date_modified: string; date_indexed: string; object: Object | null }
 
+export type FindDuplicatesArgs = { location_id: number; order: SortOrder | null; skip: number | null; take: number | null }
+
 export type GenerateThumbsForLocationArgs = { id: number; path: string }
 
 export type GetArgs = { id: number }
@@ -160,6 +202,21 @@ export type GetArgs = { id: number }
  */
 export type HashingAlgorithm = { name: "Argon2id"; params: Params } | { name: "BalloonBlake3"; params: Params }
 
+/**
+ * HealthIssue is a single problem found by [`DatabaseHealthCheck`].
+ */
+export type HealthIssue = { severity: HealthSeverity; check: string; message: string }
+
+/**
+ * HealthReport is the outcome of [`DatabaseHealthCheck::run`].
+ */
+export type HealthReport = { issues: HealthIssue[] }
+
+/**
+ * HealthSeverity is how serious a [`HealthIssue`] is.
+ */
+export type HealthSeverity = "Warning" | "Error"
+
 export type IdentifyUniqueFilesArgs = { id: number; path: string }
 
 export type IndexerRule = { id: number; pub_id: number[] | null; name: string; default: boolean; rules_per_kind: number[]; date_created: string; date_modified: string }
@@ -173,9 +230,20 @@ export type IndexerRule = { id: number; pub_id: number[] | null; name: string; d
  * 
  * In case of `RuleKind::AcceptIfChildrenDirectoriesArePresent` or `RuleKind::RejectIfChildrenDirectoriesArePresent` the
  * `parameters` field must be a vector of strings containing the names of the directories.
+ * 
+ * The remaining kinds take exactly one parameter: a size in bytes for `RuleKind::MinSize` and
+ * `RuleKind::MaxSize`, a MIME type such as `image/png` or `image/*` for `RuleKind::MimeType`, a regex
+ * for `RuleKind::NameRegex` and a RFC 3339 date for `RuleKind::CreatedBefore`.
  */
 export type IndexerRuleCreateArgs = { name: string; dry_run: boolean; rules: ([RuleKind, string[]])[] }
 
+export type IndexingError = { message: string }
+
+/**
+ * `IndexingJobReport` is emitted as a [`CoreEvent`] while an indexer job runs, so the frontend can show its progress.
+ */
+export type IndexingJobReport = { location_id: string; files_discovered: string; files_indexed: string; errors: IndexingError[]; elapsed: string }
+
 export type InvalidateOperationEvent = { key: string; arg: any; result: any | null }
 
 export type JobReport = { id: string; name: string; action: string | null; data: number[] | null; metadata: any | null; is_background: boolean; errors_text: string[]; created_at: string | null; started_at: string | null; completed_at: string | null; parent_id: string | null; status: JobStatus; task_count: number; completed_task_count: number; message: string; estimated_completion: string }
@@ -196,6 +264,14 @@ export type LibraryConfig = { name: string; description: string }
 
 export type LibraryConfigWrapped = { uuid: string; config: LibraryConfig }
 
+/**
+ * LibraryHint is how a peer advertises one of its libraries, without broadcasting the library's id over mDNS.
+ * 
+ * It's derived from the library id with BLAKE3, so it can't be turned back into the id,
+ * but a peer that has the library too can derive the same hint and tell they have it in common.
+ */
+export type LibraryHint = string
+
 export type LightScanArgs = { location_id: number; sub_path: string }
 
 export type Location = { id: number; pub_id: number[]; node_id: number; name: string; path: string; total_capacity: number | null; available_capacity: number | null; is_archived: boolean; generate_preview_media: boolean; sync_preview_media: boolean; hidden: boolean; date_created: string }
@@ -274,7 +350,7 @@ export type OwnedOperationItem = { id: any; data: OwnedOperationData }
 /**
  * TODO: P2P event for the frontend
  */
-export type P2PEvent = { type: "DiscoveredPeer"; peer_id: PeerId; metadata: PeerMetadata } | { type: "SpacedropRequest"; id: string; peer_id: PeerId; name: string }
+export type P2PEvent = { type: "DiscoveredPeer"; peer_id: PeerId; metadata: PeerMetadata } | { type: "SpacedropRequest"; id: string; peer_id: PeerId; name: string } | { type: "SyncCandidate"; peer_id: PeerId; library_id: string }
 
 /**
  * These parameters define the password-hashing level.
@@ -285,7 +361,7 @@ export type Params = "Standard" | "Hardened" | "Paranoid"
 
 export type PeerId = string
 
-export type PeerMetadata = { name: string; operating_system: OperatingSystem | null; version: string | null; email: string | null; img_url: string | null }
+export type PeerMetadata = { name: string; operating_system: OperatingSystem | null; version: string | null; email: string | null; img_url: string | null; libraries: LibraryHint[] }
 
 export type Protected<T> = T
 
@@ -295,9 +371,14 @@ export type RelationOperationData = "Create" | { Update: { field: string; value:
 
 export type RenameFileArgs = { location_id: number; file_name: string; new_file_name: string }
 
+/**
+ * `RenameStrategy` decides how a conflicting file is renamed by [`FileConflictResolution::Rename`].
+ */
+export type RenameStrategy = "Incrementing" | "Timestamp"
+
 export type RestoreBackupArgs = { password: Protected<string>; secret_key: Protected<string>; path: string }
 
-export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent"
+export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent" | "MinSize" | "MaxSize" | "MimeType" | "NameRegex" | "CreatedBefore"
 
 /**
  * This should be used for passing a salt around.
@@ -306,6 +387,10 @@ export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChil
  */
 export type Salt = number[]
 
+export type SaveVirtualDirectoryArgs = { name: string; directory: VirtualDirectory }
+
+export type SavedVirtualDirectory = { name: string; directory: VirtualDirectory }
+
 export type SearchData<T> = { cursor: number[] | null; items: T[] }
 
 export type SetFavoriteArgs = { id: number; favorite: boolean }
@@ -318,6 +403,8 @@ export type SharedOperationCreateData = { u: { [key: string]: any } } | "a"
 
 export type SharedOperationData = SharedOperationCreateData | { field: string; value: any } | null
 
+export type SmartAlbumQuery = { kinds: number[]; tags: string[]; favorite: boolean | null; hidden: boolean | null; created_after: string | null; created_before: string | null; name_contains: string | null }
+
 export type SortOrder = "Asc" | "Desc"
 
 export type SpacedropArgs = { peer_id: PeerId; file_path: string[] }
@@ -329,7 +416,7 @@ export type Statistics = { id: number; date_captured: string; total_object_count
  * 
  * It contains no sensitive information that is not encrypted.
  */
-export type StoredKey = { uuid: string; version: StoredKeyVersion; key_type: StoredKeyType; algorithm: Algorithm; hashing_algorithm: HashingAlgorithm; content_salt: Salt; master_key: EncryptedKey; master_key_nonce: Nonce; key_nonce: Nonce; key: number[]; salt: Salt; memory_only: boolean; automount: boolean }
+export type StoredKey = { uuid: string; version: StoredKeyVersion; key_type: StoredKeyType; algorithm: Algorithm; hashing_algorithm: HashingAlgorithm; content_salt: Salt; master_key: EncryptedKey; master_key_nonce: Nonce; key_nonce: Nonce; key: number[]; salt: Salt; memory_only: boolean; automount: boolean; expires_at: string | null }
 
 /**
  * This denotes the type of key. `Root` keys can be used to unlock the key manager, and `User` keys are ordinary keys.
@@ -349,6 +436,21 @@ export type TagCreateArgs = { name: string; color: string }
 
 export type TagUpdateArgs = { id: number; name: string | null; color: string | null }
 
+export type ThumbnailProgress = { total: string; completed: string; failed: string; current_path: string | null }
+
 export type UnlockKeyManagerArgs = { password: Protected<string>; secret_key: Protected<string> }
 
+/**
+ * VacuumStats describes how much space [`vacuum_library`] reclaimed.
+ */
+export type VacuumStats = { size_before: string; size_after: string; duration: string }
+
+export type VirtualDirectory = { id: string; kind: VirtualDirectoryKind }
+
+export type VirtualDirectoryArgs = { directory: VirtualDirectory; skip: number | null; take: number | null }
+
+export type VirtualDirectoryKind = { TagView: string[] } | { DateView: DateGrouping } | { SmartAlbum: SmartAlbumQuery }
+
+export type VirtualEntry = { type: "File"; item: ObjectWithFilePaths } | { type: "Directory"; name: string; directory: VirtualDirectory }
+
 export type Volume = { name: string; mount_point: string; total_capacity: string; available_capacity: string; is_removable: boolean; disk_type: DiskType | null; file_system: string | null; is_root_filesystem: boolean }